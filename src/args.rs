@@ -1,14 +1,25 @@
-use clap::{arg, builder::ArgPredicate, command, value_parser, CommandFactory, Parser};
+use anyhow::{anyhow, bail};
+use clap::{builder::ArgPredicate, value_parser, ArgGroup, CommandFactory, Parser, Subcommand};
 use clap_complete::Shell;
 use flexi_logger::Logger;
-use log::{debug, error, warn};
+use log::{debug, error, info, warn};
 use regex::Regex;
 use std::{
     env::consts::{ARCH, OS},
-    path::Path,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 
-use crate::{constants, log_format, Evaluation, NixpkgsChannelVersion};
+use crate::{
+    backends::{BackendKind, CacheMode, FixtureMode, Http, DEFAULT_RETRIES},
+    config::{Config, HostProfile},
+    constants,
+    error::{JsonResult, Reported},
+    log_format, package_lists,
+    queries::DEFAULT_CHANNELS,
+    render::{OutputFormat, Renderer},
+    Auth, Evaluation, HydraClient, HydraError, NixpkgsChannelVersion,
+};
 
 const DEFAULT_CHANNEL: &str = "unstable";
 
@@ -17,10 +28,65 @@ pub(crate) enum Queries {
     Jobset,
     Packages(Vec<String>),
     Evals(Vec<Evaluation>),
+    Builds(Vec<u64>),
+    Bisect(String),
+    CommonGood(Vec<String>),
+    /// Pairs of each package and its job names for each of the `columns`,
+    /// which are pairs of a label (the arch or channel) and a jobset
+    Matrix {
+        packages: Vec<(String, Vec<String>)>,
+        columns: Vec<(String, String)>,
+        details: bool,
+    },
+    Contains {
+        commit: String,
+        /// Pairs of the channel (if any) and the jobset to check
+        targets: Vec<(Option<String>, String)>,
+        git_dir: Option<PathBuf>,
+    },
+}
+
+/// Validates the (possibly abbreviated) hash of a git `commit`.
+fn guess_commit(commit: &str) -> anyhow::Result<String> {
+    let commit = commit.trim().to_lowercase();
+    if !Regex::new(r"^[0-9a-f]{7,40}$").unwrap().is_match(&commit) {
+        bail!("'{commit}' does not look like the hash of a git commit");
+    }
+    Ok(commit)
+}
+
+/// Parses a positive number of requests per second for `--rate-limit`.
+fn parse_rate_limit(rate_limit: &str) -> anyhow::Result<f64> {
+    match rate_limit.parse::<f64>()? {
+        x if x.is_finite() && x > 0.0 => Ok(x),
+        _ => bail!("the rate limit should be a positive number of requests per second"),
+    }
+}
+
+/// Returns the aggregate job for the release tests of a `jobset`,
+/// which decides whether the channel advances; see the `job` keys in:
+/// - <https://github.com/NixOS/infra/blob/main/channels.nix>, and
+/// - <https://status.nixos.org/>
+pub(crate) fn aggregate_job(jobset: &str) -> &'static str {
+    match jobset {
+        x if x.ends_with("darwin") => "darwin-tested",
+        x if x.starts_with("nixpkgs/") => "unstable",
+        x if x.starts_with("nixos/") => "tested",
+        _ => {
+            let default = "tested";
+            warn!("unknown --jobset '{jobset}', assuming job '{default}' for release tests");
+            default
+        }
+    }
 }
 
-#[derive(Parser, Debug, Default)]
+#[derive(Parser, Debug, Default, Clone)]
 #[command(author, version, verbatim_doc_comment)]
+#[command(group(
+    ArgGroup::new("sources")
+        .multiple(true)
+        .args(["PACKAGES", "from_file", "from_system", "from_profile", "list"])
+))]
 #[allow(
     rustdoc::bare_urls,
     clippy::doc_markdown,
@@ -50,14 +116,43 @@ pub struct HydraCheckCli {
     #[arg(id = "PACKAGES")]
     queries: Vec<String>,
 
+    /// Read packages from a file (or `-` for stdin), one per line,
+    /// with `#` for comments
+    #[arg(long, value_name = "PATH")]
+    from_file: Vec<PathBuf>,
+
+    /// Check the packages of a named list from the configuration file
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["eval", "build"])]
+    list: Vec<String>,
+
+    /// Check the packages of the current NixOS system,
+    /// guessed from /run/current-system/sw
+    #[arg(long, conflicts_with_all = ["eval", "build"])]
+    from_system: bool,
+
+    /// Check the nixpkgs packages installed with `nix profile`
+    #[arg(
+        long,
+        value_name = "PROFILE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "~/.nix-profile",
+        conflicts_with_all = ["eval", "build"]
+    )]
+    from_profile: Option<PathBuf>,
+
     /// Only print the hydra build url, then exit
-    #[arg(long)]
+    #[arg(long, global = true)]
     url: bool,
 
-    /// Output json
-    #[arg(long)]
+    /// Output json, same as --format json
+    #[arg(long, global = true, conflicts_with = "format")]
     json: bool,
 
+    /// Output format of the results
+    #[arg(long, global = true, value_enum, value_name = "FORMAT")]
+    format: Option<OutputFormat>,
+
     /// Write only the latest build even if last build failed
     #[arg(short, long)]
     short: bool,
@@ -70,43 +165,175 @@ pub struct HydraCheckCli {
     )]
     long: bool,
 
-    /// System architecture to check
-    #[arg(short, long)]
+    /// System architecture to check, or `all` (or a comma-separated list)
+    /// for a matrix of the known architectures
+    #[arg(short, long, global = true)]
     arch: Option<String>,
 
     /// Channel to check packages for
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     channel: Option<String>,
 
     /// Specify jobset to check packages for
-    #[arg(long, conflicts_with = "channel")]
+    #[arg(long, global = true, conflicts_with = "channel")]
     jobset: Option<String>,
 
     /// Print details about specific evaluations instead of packages
     #[arg(short, long)]
     eval: bool,
 
+    /// Print details about specific builds (by ID) instead of packages
+    #[arg(short, long, conflicts_with_all = ["eval", "log"])]
+    build: bool,
+
     /// Query the release tests of the given channel (jobset)
     #[arg(
-        short, long, conflicts_with_all = ["PACKAGES", "eval"],
+        short, long, conflicts_with_all = ["sources", "eval", "build"],
         // --releases implies --tests
         default_value_if("releases", ArgPredicate::IsPresent, "true")
     )]
     tests: bool,
 
     /// Combine information from channel evals and release --tests
-    #[arg(short, long, conflicts_with_all = ["PACKAGES", "eval"])]
+    #[arg(short, long, conflicts_with_all = ["sources", "eval", "build"])]
     releases: bool,
 
+    /// Find the newest evaluation where all the given packages succeeded,
+    /// with its nixpkgs revision
+    #[arg(
+        long,
+        requires = "sources",
+        conflicts_with_all = ["eval", "build", "tests", "releases", "log", "watch"]
+    )]
+    common_good: bool,
+
+    /// Compare the packages side by side across several channels,
+    /// e.g. `unstable,stable,staging-next`
+    #[arg(
+        long,
+        value_name = "CHANNELS",
+        value_delimiter = ',',
+        requires = "sources",
+        conflicts_with_all = ["channel", "jobset", "eval", "build", "tests", "common_good", "log", "watch"]
+    )]
+    compare_channels: Vec<String>,
+
+    /// Print the last lines of the log of the latest build of each package
+    /// if it has failed, following the failed dependency if necessary
+    #[arg(
+        long,
+        value_name = "LINES",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "50",
+        conflicts_with_all = ["eval", "url"]
+    )]
+    log: Option<usize>,
+
+    /// Data source for the Hydra reports
+    #[arg(long, global = true, value_enum, default_value_t)]
+    backend: BackendKind,
+
+    /// Number of packages or evaluations to fetch concurrently
+    #[arg(short, long, global = true, default_value_t = 1, value_parser = value_parser!(u16).range(1..))]
+    jobs: u16,
+
+    /// Only use responses from the on-disk cache, without network access
+    #[arg(long, global = true)]
+    offline: bool,
+
+    /// Neither read from nor write to the on-disk cache
+    #[arg(long, global = true, conflicts_with = "offline")]
+    no_cache: bool,
+
+    /// Retry failed requests (e.g. 502, 503 or timeouts) with a backoff,
+    /// at most the given times [default: 3]
+    #[arg(long, global = true, value_name = "N")]
+    retries: Option<u32>,
+
+    /// Send at most the given number of requests per second
+    #[arg(long, global = true, value_name = "RPS", value_parser = parse_rate_limit)]
+    rate_limit: Option<f64>,
+
+    /// Save every fetched page to the given directory, e.g. for a bug report
+    #[arg(long, global = true, value_name = "DIR", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Serve every page from a directory saved with --record, without
+    /// network access
+    #[arg(long, global = true, value_name = "DIR")]
+    replay: Option<PathBuf>,
+
+    /// Poll until nothing is queued anymore, printing only the changes
+    #[arg(short, long, conflicts_with_all = ["url", "json", "format", "offline", "build"])]
+    watch: bool,
+
+    /// Seconds between two polls with --watch
+    #[arg(long, default_value_t = 60, value_parser = value_parser!(u64).range(10..), requires = "watch")]
+    interval: u64,
+
+    /// Give up --watch after the given seconds, with the exit code 124
+    #[arg(long, requires = "watch")]
+    timeout: Option<u64>,
+
     /// Print more debugging information
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     verbose: bool,
 
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Hydra instance to query, by its name in the configuration or its URL
+    #[arg(long, global = true, value_name = "NAME|URL")]
+    host: Option<String>,
+
+    /// Settings of the Hydra instance, resolved from --host
+    #[arg(skip)]
+    host_profile: Option<HostProfile>,
+
     /// Print generated completions for a given shell
     #[arg(long = "shell-completion", exclusive = true, value_parser = value_parser!(Shell))]
     shell: Option<Shell>,
 }
 
+/// Subcommands for queries that go beyond a single Hydra page
+#[derive(Subcommand, Debug, Clone)]
+#[deny(missing_docs)]
+enum Command {
+    /// Find the first failing evaluation of a package that used to succeed,
+    /// along with the range of nixpkgs revisions in between
+    Bisect {
+        /// Package to bisect, e.g. `hello`
+        package: String,
+    },
+    /// Compare two evaluations, possibly of different jobsets, for the
+    /// newly failing, newly succeeding, new and removed jobs
+    EvalDiff {
+        /// ID of the evaluation to compare with, e.g. of nixpkgs/unstable
+        base: u64,
+        /// ID of the evaluation to compare, optionally with a filter like
+        /// `--eval`, e.g. `1809585/hello` (or `0` for the latest evaluation
+        /// of the jobset, e.g. of --jobset nixpkgs/staging-next)
+        eval: String,
+        /// Filter for the jobs, e.g. `/hello` (also `/` for no filter)
+        filter: Option<String>,
+    },
+    /// Find the first evaluation of each channel that contains a nixpkgs
+    /// commit, whether its release tests passed, and whether the channel
+    /// has advanced past it
+    Contains {
+        /// Nixpkgs commit, e.g. the merge commit of a pull request
+        commit: String,
+        /// Channels to check, defaults to the unstable channels unless
+        /// --channel or --jobset is given
+        channels: Vec<String>,
+        /// Local checkout of nixpkgs for the git ancestry of the commit,
+        /// instead of the compare API of GitHub
+        #[arg(long, value_name = "DIR")]
+        git_dir: Option<PathBuf>,
+    },
+}
+
 /// Resolved command line arguments, with all options normalized and unwrapped
 #[derive(Debug)]
 #[allow(clippy::struct_excessive_bools)]
@@ -114,29 +341,145 @@ pub(crate) struct ResolvedArgs {
     /// List of packages or evals to query
     pub(crate) queries: Queries,
     pub(crate) url: bool,
-    pub(crate) json: bool,
+    pub(crate) format: OutputFormat,
     pub(crate) short: bool,
     pub(crate) long: bool,
     pub(crate) releases: bool,
     pub(crate) channel: Option<String>,
     pub(crate) jobset: String,
+    pub(crate) log: Option<usize>,
+    pub(crate) jobs: usize,
+    pub(crate) watch: bool,
+    pub(crate) watch_interval: Duration,
+    pub(crate) watch_timeout: Option<Duration>,
+    pub(crate) client: HydraClient,
 }
 
 impl HydraCheckCli {
+    /// HTTP transport with the retries and the rate limit, for requests
+    /// that are not made through the [`HydraClient`].
+    fn http(&self) -> Http {
+        let http = Http::new(self.cache_mode()).with_throttle(self.retries(), self.rate_limit);
+        match self.fixtures() {
+            Some((mode, dir)) => http.with_fixtures(mode, dir),
+            None => http,
+        }
+    }
+
+    fn fixtures(&self) -> Option<(FixtureMode, &Path)> {
+        match (&self.record, &self.replay) {
+            (Some(dir), _) => Some((FixtureMode::Record, dir)),
+            (_, Some(dir)) => Some((FixtureMode::Replay, dir)),
+            _ => None,
+        }
+    }
+
+    fn retries(&self) -> u32 {
+        self.retries.unwrap_or(DEFAULT_RETRIES)
+    }
+
+    fn cache_mode(&self) -> CacheMode {
+        match (self.offline, self.no_cache, self.watch) {
+            (true, _, _) => CacheMode::Offline,
+            (_, true, _) => CacheMode::Disabled,
+            // only what has settled could be reused when polling
+            (_, _, true) => CacheMode::Refresh,
+            _ => CacheMode::Enabled,
+        }
+    }
+
+    /// Fills in the defaults from the configuration files, which are only
+    /// used if the corresponding flags are not given.
+    fn with_config(self, config: Config) -> anyhow::Result<Self> {
+        let mut queries = self.queries;
+        for name in &self.list {
+            let Some(packages) = config.lists.get(name) else {
+                let names: Vec<_> = config.lists.keys().collect();
+                bail!("no --list '{name}' in the configuration, available lists: {names:?}");
+            };
+            queries.extend(packages.iter().cloned());
+        }
+        let explicit_jobset = self.channel.is_some() || self.jobset.is_some();
+        let host_profile = config.host_profile(self.host.as_deref())?;
+        Ok(Self {
+            queries,
+            channel: match explicit_jobset {
+                true => self.channel,
+                false => config.channel,
+            },
+            arch: self.arch.or(config.arch),
+            format: self
+                .format
+                .or(self.json.then_some(OutputFormat::Json))
+                .or(config.format),
+            retries: self.retries.or(config.retries),
+            rate_limit: self.rate_limit.or(config.rate_limit),
+            host_profile,
+            ..self
+        })
+    }
+
+    /// Adds the packages from --from-file, stdin (`-`), --from-system and
+    /// --from-profile to the packages on the command line.
+    fn read_package_lists(self) -> anyhow::Result<Self> {
+        let mut queries = Vec::new();
+        for query in self.queries {
+            match query.as_str() {
+                "-" => queries.extend(package_lists::from_file(Path::new("-"))?),
+                _ => queries.push(query),
+            }
+        }
+        for path in &self.from_file {
+            queries.extend(package_lists::from_file(path)?);
+        }
+        if self.from_system {
+            info!("guessing the attribute names from the store paths of the current system");
+            queries.extend(package_lists::from_system()?);
+        }
+        if let Some(profile) = &self.from_profile {
+            let profile = match profile.strip_prefix("~") {
+                Ok(x) => {
+                    let home =
+                        std::env::var_os("HOME").ok_or_else(|| anyhow!("$HOME is not set"))?;
+                    Path::new(&home).join(x)
+                }
+                Err(_) => profile.clone(),
+            };
+            queries.extend(package_lists::from_profile(&profile)?);
+        }
+        Ok(Self { queries, ..self })
+    }
+
+    /// Architectures of the Hydra instance, from the --host profile.
+    fn known_archs(&self) -> Vec<&str> {
+        match self
+            .host_profile
+            .as_ref()
+            .and_then(|x| x.architectures.as_ref())
+        {
+            Some(archs) => archs.iter().map(String::as_str).collect(),
+            None => constants::KNOWN_ARCHITECTURES.to_vec(),
+        }
+    }
+
     fn guess_arch(self) -> Self {
+        let known_archs = self.known_archs();
         let warn_if_unknown = |arch: &str| {
-            if !Vec::from(constants::KNOWN_ARCHITECTURES).contains(&arch) {
+            if !known_archs.contains(&arch) {
                 warn!(
                     "unknown --arch '{arch}', {}: {:#?}",
-                    "consider specifying one of the following known architectures",
-                    constants::KNOWN_ARCHITECTURES
+                    "consider specifying one of the following known architectures", known_archs
                 );
             }
         };
         if let Some(arch) = self.arch.clone() {
             // allow empty `--arch` as it may be the user's intention to
             // specify architectures explicitly for each package
-            if !arch.is_empty() {
+            if let Some(archs) = self.guess_matrix_archs() {
+                for arch in &archs {
+                    warn_if_unknown(arch);
+                }
+            } else if !arch.is_empty() {
                 warn_if_unknown(&arch);
             }
             return self;
@@ -171,6 +514,21 @@ impl HydraCheckCli {
                 ..self
             };
         }
+        let host_jobset = self.host_profile.as_ref().and_then(|host| {
+            match &self.channel {
+                Some(channel) => host.channels.get(channel),
+                None => host.jobset.as_ref(),
+            }
+            .cloned()
+        });
+        if let Some(jobset) = host_jobset {
+            debug!("--host implies --jobset '{jobset}'");
+            return Self {
+                jobset: Some(jobset),
+                ..self
+            };
+        }
+        let http = self.http();
         let channel = self.channel.unwrap_or(DEFAULT_CHANNEL.into());
         // https://wiki.nixos.org/wiki/Channel_branches
         // https://github.com/NixOS/infra/blob/master/channels.nix
@@ -196,7 +554,7 @@ impl HydraCheckCli {
                 _ => nixpkgs_unstable.into(),
             },
             "stable" => {
-                let version = match NixpkgsChannelVersion::stable() {
+                let version = match NixpkgsChannelVersion::stable_with(&http) {
                     Ok(version) => version,
                     Err(err) => {
                         error!(
@@ -244,7 +602,8 @@ impl HydraCheckCli {
     /// See the source code for the detailed heuristics.
     #[must_use]
     pub fn guess_package_name(&self, package: &str) -> String {
-        let has_known_arch_suffix = constants::KNOWN_ARCHITECTURES
+        let has_known_arch_suffix = self
+            .known_archs()
             .iter()
             .any(|known_arch| package.ends_with(format!(".{known_arch}").as_str()));
 
@@ -258,12 +617,12 @@ impl HydraCheckCli {
             "".into()
         };
 
-        let arch_suffix = match self.arch.clone() {
+        let arch_suffix = match self.arch.as_deref() {
             _ if has_known_arch_suffix => "".into(),
             None => warn_unknown_arch(),
             // empty --arch is useful for aggregate job such as the channel tests
             // e.g. https://hydra.nixos.org/job/nixpkgs/unstable/unstable
-            Some(arch) if arch.is_empty() => "".into(),
+            Some("") => "".into(),
             Some(arch) => format!(".{arch}"),
         };
 
@@ -280,46 +639,160 @@ impl HydraCheckCli {
         format!("{package}{arch_suffix}")
     }
 
+    /// Returns the architectures of `--arch all` or `--arch x,y,...`.
+    fn guess_matrix_archs(&self) -> Option<Vec<String>> {
+        match self.arch.as_deref()? {
+            "all" => Some(self.known_archs().into_iter().map(str::to_string).collect()),
+            x if x.contains(',') => Some(
+                x.split(',')
+                    .map(str::trim)
+                    .filter(|x| !x.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Rejects the options that only support a single `--arch`, before a
+    /// matrix of multiple `--arch` is chosen for the queries.
+    fn check_single_arch(&self) -> anyhow::Result<()> {
+        if self.common_good && self.guess_matrix_archs().is_some() {
+            bail!("--common-good only supports a single --arch");
+        }
+        Ok(())
+    }
+
+    /// Pairs each of the packages with its job names for the `archs`, along
+    /// with the jobset of each arch, which differs on darwin for the stable
+    /// channels. The jobsets are guessed from scratch, so this is called
+    /// before [`Self::guess_jobset`].
+    fn guess_matrix(&self, archs: &[String]) -> Queries {
+        let per_arch: Vec<_> = archs
+            .iter()
+            .map(|arch| {
+                Self {
+                    arch: Some(arch.clone()),
+                    ..self.clone()
+                }
+                .guess_jobset()
+            })
+            .collect();
+        let packages = self
+            .valid_queries()
+            .map(|package| {
+                let jobs = per_arch
+                    .iter()
+                    .map(|args| args.guess_package_name(package))
+                    .collect();
+                (package.clone(), jobs)
+            })
+            .collect();
+        let columns = archs
+            .iter()
+            .cloned()
+            .zip(per_arch.into_iter().map(|x| x.jobset.unwrap_or_default()))
+            .collect();
+        Queries::Matrix {
+            packages,
+            columns,
+            details: false,
+        }
+    }
+
+    fn valid_queries(&self) -> impl Iterator<Item = &String> {
+        self.queries.iter().filter(|package| {
+            if package.starts_with("python3Packages") || package.starts_with("python3.pkgs") {
+                error!(
+                    "instead of '{package}', you want {}",
+                    "python3xPackages... (e.g. python311Packages)"
+                );
+                false
+            } else {
+                true
+            }
+        })
+    }
+
     fn guess_packages(&self) -> Vec<String> {
         if self.tests {
             let Some(ref jobset) = self.jobset else {
                 error!("--jobset is not properly set up or deduced");
                 std::process::exit(1);
             };
-            // aggregate job for channel release tests; see the `job` keys in:
-            // - https://github.com/NixOS/infra/blob/main/channels.nix, and
-            // - https://status.nixos.org/
-            //
-            let aggregate_job = match jobset.as_str() {
-                x if x.ends_with("darwin") => "darwin-tested",
-                x if x.starts_with("nixpkgs/") => "unstable",
-                x if x.starts_with("nixos/") => "tested",
-                _ => {
-                    let default = "tested";
-                    warn!(
-                        "unknown --jobset '{jobset}', assuming job '{default}' for release tests"
-                    );
-                    default
-                }
-            };
-            return vec![aggregate_job.into()];
+            return vec![aggregate_job(jobset).into()];
         }
-        self.queries
-            .iter()
-            .filter_map(|package| {
-                if package.starts_with("python3Packages") || package.starts_with("python3.pkgs") {
-                    error!(
-                        "instead of '{package}', you want {}",
-                        "python3xPackages... (e.g. python311Packages)"
-                    );
-                    None
-                } else {
-                    Some(self.guess_package_name(package))
-                }
+        self.valid_queries()
+            .map(|package| self.guess_package_name(package))
+            .collect()
+    }
+
+    /// Resolves the `channels` into their jobsets, falling back to the
+    /// resolved --channel or --jobset if it is `explicit`, and otherwise
+    /// to the [`DEFAULT_CHANNELS`].
+    fn guess_targets(&self, channels: &[String], explicit: bool) -> Vec<(Option<String>, String)> {
+        let channels: Vec<String> = match (channels.is_empty(), explicit) {
+            (false, _) => channels.to_vec(),
+            (true, true) => {
+                let jobset = self.jobset.clone().unwrap_or_default();
+                return vec![(self.channel.clone(), jobset)];
+            }
+            (true, false) => DEFAULT_CHANNELS.map(str::to_string).to_vec(),
+        };
+        channels
+            .into_iter()
+            .map(|channel| {
+                let args = self.with_channel(channel);
+                let jobset = args.jobset.unwrap_or_default();
+                (args.channel, jobset)
             })
             .collect()
     }
 
+    /// Guesses the jobset of another `channel`, with the other arguments
+    /// unchanged.
+    fn with_channel(&self, channel: String) -> Self {
+        Self {
+            channel: Some(channel),
+            jobset: None,
+            ..self.clone()
+        }
+        .guess_jobset()
+    }
+
+    /// Pairs each of the packages with its job names for the `channels`,
+    /// along with the jobsets of the channels.
+    fn guess_compare(&self, channels: &[String]) -> Queries {
+        let per_channel: Vec<_> = channels
+            .iter()
+            .map(|channel| self.with_channel(channel.clone()))
+            .collect();
+        let packages = self
+            .valid_queries()
+            .map(|package| {
+                let jobs = per_channel
+                    .iter()
+                    .map(|args| args.guess_package_name(package))
+                    .collect();
+                (package.clone(), jobs)
+            })
+            .collect();
+        let columns = channels
+            .iter()
+            .cloned()
+            .zip(
+                per_channel
+                    .into_iter()
+                    .map(|x| x.jobset.unwrap_or_default()),
+            )
+            .collect();
+        Queries::Matrix {
+            packages,
+            columns,
+            details: true,
+        }
+    }
+
     fn guess_evals(&self) -> Vec<Evaluation> {
         if self.queries.is_empty() {
             // this would resolve to the latest eval of a jobset:
@@ -332,6 +805,25 @@ impl HydraCheckCli {
         evals
     }
 
+    fn guess_builds(&self) -> anyhow::Result<Vec<u64>> {
+        if self.queries.is_empty() {
+            bail!("please specify the IDs of the builds for --build");
+        }
+        self.queries
+            .iter()
+            .map(|spec| {
+                // also accept the URLs of builds, e.g. https://hydra.nixos.org/build/123
+                let id = spec
+                    .trim_end_matches('/')
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or(spec);
+                id.parse()
+                    .map_err(|err| anyhow!("invalid build ID '{spec}' for --build: {err}"))
+            })
+            .collect()
+    }
+
     /// Parses the command line flags and calls [`Self::guess_all_args()`].
     /// Also prints shell completions if asked for.
     pub(crate) fn parse_and_guess() -> anyhow::Result<ResolvedArgs> {
@@ -381,17 +873,82 @@ impl HydraCheckCli {
             true => log::LevelFilter::Trace,
         };
         Logger::with(log_level).format(log_format).start()?;
+        let args = args.with_config(Config::load()?)?;
+        let args = args.read_package_lists()?;
         let args = args.guess_arch();
+        let explicit_jobset = args.channel.is_some() || args.jobset.is_some();
+        let unguessed = args.clone();
         let args = args.guess_jobset();
+        args.check_single_arch()?;
+        let matrix_archs = args.guess_matrix_archs();
         let queries = match (args.eval, !args.queries.is_empty() || args.tests) {
+            _ if args.command.is_some() => match args.command.clone() {
+                Some(Command::Bisect { package }) => {
+                    Queries::Bisect(args.guess_package_name(&package))
+                }
+                Some(Command::EvalDiff { base, eval, filter }) => {
+                    let spec = match filter {
+                        Some(filter) => format!("{eval}/{}", filter.trim_start_matches('/')),
+                        None => eval,
+                    };
+                    let eval = Evaluation::guess_from_spec(&spec, args.long).compared_to(base);
+                    Queries::Evals(vec![eval])
+                }
+                Some(Command::Contains {
+                    commit,
+                    channels,
+                    git_dir,
+                }) => Queries::Contains {
+                    commit: guess_commit(&commit)?,
+                    targets: args.guess_targets(&channels, explicit_jobset),
+                    git_dir,
+                },
+                None => unreachable!(),
+            },
+            _ if args.build => Queries::Builds(args.guess_builds()?),
+            (false, true) if !args.compare_channels.is_empty() => {
+                if matrix_archs.is_some() {
+                    bail!("--compare-channels only supports a single --arch");
+                }
+                args.guess_compare(&args.compare_channels)
+            }
+            (false, true) if matrix_archs.is_some() && !args.tests => {
+                unguessed.guess_matrix(matrix_archs.as_deref().unwrap_or_default())
+            }
             (true, _) => Queries::Evals(args.guess_evals()),
+            (_, true) if args.common_good => Queries::CommonGood(args.guess_packages()),
             (_, true) => Queries::Packages(args.guess_packages()),
             (_, false) => Queries::Jobset,
         };
+        if matrix_archs.is_some()
+            && !args.tests
+            && matches!(queries, Queries::Packages(_) | Queries::Bisect(_))
+        {
+            bail!("a matrix of multiple --arch is only supported for plain package queries");
+        }
+        if args.log.is_some() && !matches!(queries, Queries::Packages(_)) {
+            // e.g. the jobset, a matrix of --arch or the subcommands
+            bail!("--log is only supported for plain package queries");
+        }
+        let client = match &args.host_profile {
+            Some(host) => host.client(),
+            None => {
+                let client = HydraClient::default();
+                let auth = Auth::default().with_defaults(client.host_url());
+                client.with_auth(&auth)
+            }
+        }
+        .with_backend(args.backend)
+        .with_cache(args.cache_mode())
+        .with_throttle(args.retries(), args.rate_limit);
+        let client = match args.fixtures() {
+            Some((mode, dir)) => client.with_fixtures(mode, dir),
+            None => client,
+        };
         Ok(ResolvedArgs {
             queries,
             url: args.url,
-            json: args.json,
+            format: args.format.unwrap_or_default(),
             short: args.short,
             long: args.long,
             releases: args.releases,
@@ -399,12 +956,46 @@ impl HydraCheckCli {
             jobset: args
                 .jobset
                 .expect("jobset should be resolved by `guess_jobset()`"),
+            log: args.log,
+            jobs: args.jobs.into(),
+            watch: args.watch,
+            watch_interval: Duration::from_secs(args.interval),
+            watch_timeout: args.timeout.map(Duration::from_secs),
+            client,
         })
     }
 
     /// Runs the program and provides an exit code (with possible errors).
+    /// A [`HydraError`] is reported here already, as an `error` object with
+    /// `--json` (or another `--format`), and only determines the exit code,
+    /// see [`HydraError::exit_code`].
     pub fn execute() -> anyhow::Result<bool> {
-        Self::parse_and_guess()?.fetch_and_print()
+        let args =
+            Self::parse_and_guess().inspect_err(|err| report_error(err, OutputFormat::Table))?;
+        args.fetch_and_print()
+            .inspect_err(|err| report_error(err, args.format))
+    }
+}
+
+/// Reports a [`HydraError`], unless it has been printed along with the
+/// results already. Any other error is left to the caller.
+fn report_error(err: &anyhow::Error, format: OutputFormat) {
+    let Some(error) = err.downcast_ref::<HydraError>() else {
+        return;
+    };
+    if err.downcast_ref::<Reported>().is_some() {
+        return;
+    }
+    let output = JsonResult::<()>::Err {
+        error: error.clone(),
+    };
+    let print = || -> anyhow::Result<()> {
+        let mut renderer = Renderer::new(format);
+        renderer.push(&[], &output)?;
+        renderer.finish(&output)
+    };
+    if format.is_table() || print().is_err() {
+        error!("{error}");
     }
 }
 
@@ -412,14 +1003,63 @@ impl ResolvedArgs {
     /// Fetches build or evaluation status from hydra.nixos.org
     /// and prints the result according to the command line specs.
     pub(crate) fn fetch_and_print(&self) -> anyhow::Result<bool> {
-        match &self.queries {
+        if self.watch {
+            return match &self.queries {
+                Queries::Jobset => self.watch_jobset(),
+                Queries::Packages(packages) => self.watch_packages(packages),
+                Queries::Evals(evals) => self.watch_evaluations(evals),
+                Queries::Builds(_)
+                | Queries::Bisect(_)
+                | Queries::CommonGood(_)
+                | Queries::Contains { .. }
+                | Queries::Matrix { .. } => {
+                    bail!("--watch is only supported for packages, jobsets and --eval")
+                }
+            };
+        }
+        match (self.format, &self.queries) {
+            (OutputFormat::Junit, Queries::Packages(_) | Queries::Matrix { .. })
+            | (
+                OutputFormat::Prometheus,
+                Queries::Jobset | Queries::Packages(_) | Queries::Matrix { .. },
+            ) => {}
+            (OutputFormat::Junit, _) => bail!("--format junit is only supported for packages"),
+            (OutputFormat::Prometheus, _) => {
+                bail!("--format prometheus is only supported for packages and jobsets")
+            }
+            _ => {}
+        }
+        let status = match &self.queries {
             Queries::Jobset => {
                 self.fetch_and_print_jobset(false)?;
                 Ok(true)
             }
             Queries::Packages(packages) => self.fetch_and_print_packages(packages),
             Queries::Evals(evals) => self.fetch_and_print_evaluations(evals),
+            Queries::Builds(build_ids) => self.fetch_and_print_builds(build_ids),
+            Queries::Bisect(package) => self.fetch_and_print_bisect(package),
+            Queries::CommonGood(packages) => self.fetch_and_print_common_good(packages),
+            Queries::Contains {
+                commit,
+                targets,
+                git_dir,
+            } => self.fetch_and_print_contains(commit, targets, git_dir.as_deref()),
+            Queries::Matrix {
+                packages,
+                columns,
+                details,
+            } => self.fetch_and_print_matrix(packages, columns, *details),
+        };
+        if self.format == OutputFormat::Prometheus {
+            // the failed and missing jobs are in the gauges once they are
+            // printed, and a non-zero exit code would only keep them from
+            // being updated, e.g. with `hydra-check ... > x.prom.$$ && mv ...`
+            return match status {
+                Err(err) if err.downcast_ref::<Reported>().is_none() => Err(err),
+                _ => Ok(true),
+            };
         }
+        status
     }
 }
 
@@ -476,3 +1116,98 @@ fn guess_stable() {
         .jobset
         .is_some_and(|x| x.starts_with("nixpkgs/nixpkgs-") && x.ends_with("darwin")));
 }
+
+#[test]
+fn guess_commits() {
+    let commit = "5D8F4BEAC036a6f9d9b1aaf0bbc4c2ad1a0e0d8b";
+    assert_eq!(guess_commit(commit).unwrap(), commit.to_lowercase());
+    assert!(guess_commit("5d8f4be").is_ok());
+    for commit in ["5d8f4b", "nixos-unstable", "5d8f4beac036..1a0e0d8b"] {
+        assert!(guess_commit(commit).is_err());
+    }
+}
+
+#[test]
+fn guess_matrix() {
+    let args = HydraCheckCli::parse_from(["hydra-check", "hello", "--arch", "all"]);
+    let archs = args.guess_matrix_archs().unwrap();
+    assert_eq!(archs, constants::KNOWN_ARCHITECTURES);
+    let Queries::Matrix { packages, .. } = args.guess_matrix(&archs) else {
+        panic!("--arch all should resolve to a matrix");
+    };
+    assert_eq!(packages[0].1[2], "hello.x86_64-darwin");
+
+    // the stable channels have separate jobsets for darwin
+    let args = HydraCheckCli::parse_from(["hydra-check", "hello", "--arch=all", "--channel=24.05"]);
+    let Queries::Matrix {
+        packages, columns, ..
+    } = args.guess_matrix(&archs)
+    else {
+        panic!("--arch all should resolve to a matrix");
+    };
+    assert_eq!(
+        columns[1],
+        ("aarch64-linux".into(), "nixos/release-24.05".into())
+    );
+    assert_eq!(
+        columns[2],
+        (
+            "x86_64-darwin".into(),
+            "nixpkgs/nixpkgs-24.05-darwin".into()
+        )
+    );
+    assert_eq!(packages[0].1[1], "nixpkgs.hello.aarch64-linux");
+    assert_eq!(packages[0].1[2], "hello.x86_64-darwin");
+
+    let args = HydraCheckCli::parse_from(["hydra-check", "--arch", "aarch64-linux, x86_64-darwin"]);
+    assert_eq!(
+        args.guess_matrix_archs().unwrap(),
+        ["aarch64-linux", "x86_64-darwin"]
+    );
+    let args = HydraCheckCli::parse_from(["hydra-check", "--arch", "aarch64-linux"]);
+    assert!(args.guess_matrix_archs().is_none());
+}
+
+#[test]
+fn guess_common_good_arch() {
+    let args =
+        HydraCheckCli::parse_from(["hydra-check", "hello", "--common-good", "--arch", "all"]);
+    assert!(args.check_single_arch().is_err());
+    let args = HydraCheckCli::parse_from([
+        "hydra-check",
+        "hello",
+        "--common-good",
+        "--arch",
+        "x86_64-linux,aarch64-linux",
+    ]);
+    assert!(args.check_single_arch().is_err());
+    let args = HydraCheckCli::parse_from([
+        "hydra-check",
+        "hello",
+        "--common-good",
+        "--arch",
+        "x86_64-linux",
+    ]);
+    assert!(args.check_single_arch().is_ok());
+}
+
+#[test]
+fn guess_compare() {
+    let args = HydraCheckCli::parse_from([
+        "hydra-check",
+        "hello",
+        "--arch=x86_64-linux",
+        "--compare-channels=master,24.05",
+    ]);
+    let Queries::Matrix {
+        packages, columns, ..
+    } = args.guess_compare(&args.compare_channels)
+    else {
+        panic!("--compare-channels should resolve to a matrix");
+    };
+    assert_eq!(columns[1], ("24.05".into(), "nixos/release-24.05".into()));
+    assert_eq!(
+        packages[0].1,
+        ["hello.x86_64-linux", "nixpkgs.hello.x86_64-linux"]
+    );
+}
@@ -0,0 +1,339 @@
+//! Authentication for private Hydra instances, either with a session from
+//! Hydra's `/login`, or with a bearer token and custom headers for instances
+//! behind an authenticating proxy.
+//!
+//! The session cookie is stored under `$XDG_STATE_HOME/hydra-check/sessions`,
+//! so that the login is only repeated once the session has expired. The
+//! cookie and the headers are only ever sent to the Hydra instance itself,
+//! and not to the other services that hydra-check talks to.
+
+use indexmap::IndexMap;
+use log::{debug, info, warn};
+use reqwest::{
+    blocking::Client,
+    cookie::{CookieStore, Jar},
+    header::{HeaderMap, HeaderName, HeaderValue, ACCEPT, AUTHORIZATION, REFERER},
+    Url,
+};
+use serde::Deserialize;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use super::ACCEPT_JSON;
+use crate::constants;
+
+/// Credentials for a Hydra instance, see
+/// [`HydraClient::with_auth`][crate::HydraClient::with_auth].
+#[non_exhaustive]
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Auth {
+    /// User name for the `/login` of Hydra
+    pub username: Option<String>,
+    /// Password for the `/login` of Hydra
+    pub password: Option<String>,
+    /// Bearer token for the `Authorization` header
+    pub token: Option<String>,
+    /// Custom headers, e.g. for an authenticating proxy
+    #[serde(default)]
+    pub headers: IndexMap<String, String>,
+}
+
+impl Auth {
+    /// Fills in the credentials for `host_url` from the environment variables
+    /// `$HYDRA_CHECK_USERNAME`, `$HYDRA_CHECK_PASSWORD` and `$HYDRA_CHECK_TOKEN`,
+    /// which take precedence, and then from the `machine` of the host in
+    /// `$NETRC` or `~/.netrc` if no password is known yet. The netrc is
+    /// ignored for the default Hydra instance, unless a username is given.
+    #[must_use]
+    pub fn with_defaults(self, host_url: &str) -> Self {
+        let from_env = |var: &str| std::env::var(var).ok().filter(|x| !x.is_empty());
+        let mut auth = Self {
+            username: from_env("HYDRA_CHECK_USERNAME").or(self.username),
+            password: from_env("HYDRA_CHECK_PASSWORD").or(self.password),
+            token: from_env("HYDRA_CHECK_TOKEN").or(self.token),
+            ..self
+        };
+        let public = constants::is_default_host_url(host_url) && auth.username.is_none();
+        if auth.password.is_none() && !public {
+            let host = Url::parse(host_url).ok();
+            let netrc = netrc_path().and_then(|path| std::fs::read_to_string(path).ok());
+            if let (Some(host), Some(netrc)) = (host.as_ref().and_then(Url::host_str), netrc) {
+                if let Some((login, password)) = read_netrc(&netrc, host, auth.username.as_deref())
+                {
+                    debug!("using the credentials of {login} for {host} from netrc");
+                    auth.username = Some(login);
+                    auth.password = Some(password);
+                }
+            }
+        }
+        auth
+    }
+
+    fn is_empty(&self) -> bool {
+        self.username.is_none() && self.token.is_none() && self.headers.is_empty()
+    }
+
+    /// Headers for every request to the Hydra instance, skipping the invalid ones.
+    fn header_map(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let token = self
+            .token
+            .as_ref()
+            .map(|x| ("Authorization", format!("Bearer {x}")));
+        let custom = self.headers.iter().map(|(k, v)| (k.as_str(), v.clone()));
+        for (name, value) in token.into_iter().chain(custom) {
+            match (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+                (Ok(name), Ok(mut value)) => {
+                    value.set_sensitive(name == AUTHORIZATION);
+                    headers.insert(name, value);
+                }
+                _ => warn!("skipping the invalid header '{name}'"),
+            }
+        }
+        headers
+    }
+}
+
+fn netrc_path() -> Option<PathBuf> {
+    match std::env::var_os("NETRC") {
+        Some(path) => Some(path.into()),
+        None => Some(Path::new(&std::env::var_os("HOME")?).join(".netrc")),
+    }
+}
+
+/// Finds the login and password for the `host` in a netrc file, matching the
+/// `login` if it is already known. The `default` entry is never used, so that
+/// the credentials are not sent to any other host.
+fn read_netrc(text: &str, host: &str, login: Option<&str>) -> Option<(String, String)> {
+    // machine (none for the default), login and password of each entry
+    type Entry<'a> = (Option<&'a str>, Option<&'a str>, Option<&'a str>);
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut tokens = text.split_whitespace();
+    while let Some(token) = tokens.next() {
+        let entry = entries.last_mut();
+        match (token, entry) {
+            ("machine", _) => entries.push((Some(tokens.next().unwrap_or_default()), None, None)),
+            ("default", _) => entries.push((None, None, None)),
+            ("login", Some(entry)) => entry.1 = tokens.next(),
+            ("password", Some(entry)) => entry.2 = tokens.next(),
+            ("account", _) => {
+                tokens.next();
+            }
+            // the macros are not supported, and may contain anything
+            ("macdef", _) => break,
+            _ => {}
+        }
+    }
+    entries.into_iter().find_map(|(machine, user, password)| {
+        let matches = machine == Some(host) && (login.is_none() || user == login);
+        match (matches, user, password) {
+            (true, Some(user), Some(password)) => Some((user.into(), password.into())),
+            _ => None,
+        }
+    })
+}
+
+/// Session with a Hydra instance, shared by the clones of
+/// [`Http`][super::Http].
+#[derive(Debug)]
+pub(crate) struct Session {
+    host_url: Url,
+    jar: Arc<Jar>,
+    headers: HeaderMap,
+    credentials: Option<(String, String)>,
+    /// File of the persisted session cookie
+    path: Option<PathBuf>,
+    /// Whether a login has already been attempted by this process
+    attempted: Mutex<bool>,
+}
+
+impl Session {
+    /// Creates a session for the Hydra instance at `host_url`, unless
+    /// there is no authentication at all.
+    pub(crate) fn new(host_url: &str, auth: &Auth) -> Option<Self> {
+        if auth.is_empty() {
+            return None;
+        }
+        let Ok(host_url) = Url::parse(&format!("{}/", host_url.trim_end_matches('/'))) else {
+            warn!("skipping the authentication for the invalid host URL {host_url}");
+            return None;
+        };
+        let credentials = match (&auth.username, &auth.password) {
+            (Some(username), Some(password)) => Some((username.clone(), password.clone())),
+            (Some(username), None) => {
+                warn!("no password for {username} in the configuration, netrc or environment");
+                None
+            }
+            _ => None,
+        };
+        let path = host_url.host_str().and_then(|host| {
+            let name = match host_url.port() {
+                Some(port) => format!("{host}_{port}"),
+                None => host.into(),
+            };
+            Some(state_dir()?.join("sessions").join(name))
+        });
+        let jar = Arc::new(Jar::default());
+        if let Some(cookies) = path.as_ref().and_then(|x| std::fs::read_to_string(x).ok()) {
+            debug!("using the persisted session for {host_url}");
+            for cookie in cookies.split(';').map(str::trim).filter(|x| !x.is_empty()) {
+                jar.add_cookie_str(cookie, &host_url);
+            }
+        }
+        Some(Self {
+            host_url,
+            jar,
+            headers: auth.header_map(),
+            credentials,
+            path,
+            attempted: Mutex::new(false),
+        })
+    }
+
+    pub(crate) fn jar(&self) -> Arc<Jar> {
+        self.jar.clone()
+    }
+
+    /// Whether `url` belongs to the Hydra instance of the session.
+    pub(crate) fn covers(&self, url: &str) -> bool {
+        let base = self.host_url.as_str();
+        url.starts_with(base) || url == base.trim_end_matches('/')
+    }
+
+    /// Headers for every request to the Hydra instance.
+    pub(crate) fn headers(&self) -> HeaderMap {
+        self.headers.clone()
+    }
+
+    /// Logs in with the credentials, unless a session already exists or
+    /// a login has already been attempted. With `expired`, the existing
+    /// session is ignored, e.g. when Hydra has denied a request.
+    ///
+    /// Returns whether a new session has been established. A failed login
+    /// is only reported as a warning, and the requests are then anonymous.
+    pub(crate) fn login(&self, client: &Client, expired: bool) -> bool {
+        let Some((username, password)) = &self.credentials else {
+            return false;
+        };
+        let mut attempted = self
+            .attempted
+            .lock()
+            .expect("a login should never panic while holding the lock");
+        let has_session = self.jar.cookies(&self.host_url).is_some();
+        if *attempted || (has_session && !expired) {
+            return false;
+        }
+        *attempted = true;
+        info!("logging in to {} as {username}", self.host_url);
+        let response = self
+            .host_url
+            .join("login")
+            .map_err(anyhow::Error::from)
+            .and_then(|url| {
+                let response = client
+                    .post(url)
+                    .header(ACCEPT, ACCEPT_JSON)
+                    // Hydra only accepts POST requests that refer to itself
+                    .header(REFERER, self.host_url.as_str())
+                    .headers(self.headers())
+                    .form(&[("username", username), ("password", password)])
+                    .send()?;
+                Ok(response)
+            });
+        match response {
+            Ok(response) if response.status().is_success() => {
+                self.persist();
+                true
+            }
+            Ok(response) => {
+                warn!(
+                    "could not log in to {} as {username}: {}, continuing anonymously",
+                    self.host_url,
+                    response.status()
+                );
+                false
+            }
+            Err(err) => {
+                warn!(
+                    "could not log in to {} as {username}: {err}, continuing anonymously",
+                    self.host_url
+                );
+                false
+            }
+        }
+    }
+
+    fn persist(&self) {
+        let (Some(path), Some(cookies)) = (&self.path, self.jar.cookies(&self.host_url)) else {
+            return;
+        };
+        let write = || -> std::io::Result<()> {
+            // the cookie grants access to Hydra, so it is private from the start
+            let mut dir = std::fs::DirBuilder::new();
+            let mut file = std::fs::OpenOptions::new();
+            file.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+                dir.mode(0o700);
+                file.mode(0o600);
+            }
+            if let Some(parent) = path.parent() {
+                dir.recursive(true).create(parent)?;
+            }
+            file.open(path)?.write_all(cookies.as_bytes())
+        };
+        if let Err(err) = write() {
+            warn!("could not persist the session in {}: {err}", path.display());
+        }
+    }
+}
+
+fn state_dir() -> Option<PathBuf> {
+    let from_env = |var: &str| {
+        std::env::var_os(var)
+            .map(PathBuf::from)
+            .filter(|x| x.is_absolute())
+    };
+    let dir = from_env("XDG_STATE_HOME").or_else(|| Some(from_env("HOME")?.join(".local/state")));
+    if dir.is_none() {
+        warn!("could not determine the state directory from $XDG_STATE_HOME or $HOME");
+    }
+    Some(dir?.join(env!("CARGO_PKG_NAME")))
+}
+
+#[test]
+fn netrc_credentials() {
+    let netrc = "
+        machine hydra.example.com login alice password secret
+        machine hydra.example.com
+            login bob
+            password hunter2
+        default login anonymous password guest
+    ";
+    let creds = |host, login| read_netrc(netrc, host, login);
+    assert_eq!(
+        creds("hydra.example.com", None),
+        Some(("alice".into(), "secret".into()))
+    );
+    assert_eq!(
+        creds("hydra.example.com", Some("bob")),
+        Some(("bob".into(), "hunter2".into()))
+    );
+    assert_eq!(creds("hydra.example.com", Some("carol")), None);
+    // the default entry would leak the credentials to any host
+    assert_eq!(creds("hydra.nixos.org", None), None);
+
+    let auth = Auth {
+        token: Some("abc".into()),
+        headers: [("X-Forwarded-User".into(), "alice".into())].into(),
+        ..Default::default()
+    };
+    let headers = auth.header_map();
+    assert_eq!(headers[AUTHORIZATION], "Bearer abc");
+    assert_eq!(headers["x-forwarded-user"], "alice");
+}
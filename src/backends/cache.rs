@@ -0,0 +1,224 @@
+//! On-disk cache of HTTP responses, stored under `$XDG_CACHE_HOME/hydra-check`.
+//!
+//! Each response is stored as a single JSON file, keyed by its URL and the
+//! `Accept` header of the request. Entries expire after a time to live that
+//! depends on the kind of page, unless they are marked as _settled_ (e.g. the
+//! page of a finished evaluation), in which case they never change again.
+//! The cache is best-effort: any error while reading or writing it is only
+//! logged, and the request then goes to the network as usual.
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{Page, ACCEPT_HTML, ACCEPT_JSON, ACCEPT_TEXT};
+
+/// Usage of the on-disk cache of HTTP responses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CacheMode {
+    /// Always fetch from the network
+    #[default]
+    Disabled,
+    /// Serve fresh responses from the cache and store new ones
+    Enabled,
+    /// Serve only from the cache, regardless of freshness
+    Offline,
+    /// Serve only settled responses from the cache and store new ones,
+    /// for polling Hydra repeatedly
+    Refresh,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    url: String,
+    accept: String,
+    content_type: Option<String>,
+    fetched_at: u64,
+    settled: bool,
+    body: String,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct HttpCache {
+    dir: Option<PathBuf>,
+    mode: CacheMode,
+}
+
+impl HttpCache {
+    pub(crate) fn new(mode: CacheMode) -> Self {
+        let dir = match mode {
+            CacheMode::Disabled => None,
+            _ => cache_dir(),
+        };
+        debug!("using the cache directory {dir:?} in the mode {mode:?}");
+        Self { dir, mode }
+    }
+
+    pub(crate) fn mode(&self) -> CacheMode {
+        self.mode
+    }
+
+    fn path(&self, url: &str, accept: &str) -> Option<PathBuf> {
+        let key = fnv1a(&format!("{accept} {url}"));
+        Some(self.dir.as_ref()?.join(format!("{key:016x}.json")))
+    }
+
+    fn read(&self, url: &str, accept: &str) -> Option<CacheEntry> {
+        let path = self.path(url, accept)?;
+        let text = std::fs::read_to_string(path).ok()?;
+        let entry: CacheEntry = serde_json::from_str(&text).ok()?;
+        // guard against (however unlikely) hash collisions
+        (entry.url == url && entry.accept == accept).then_some(entry)
+    }
+
+    fn write(&self, entry: &CacheEntry) {
+        let Some(path) = self.path(&entry.url, &entry.accept) else {
+            return;
+        };
+        let write = || -> anyhow::Result<()> {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            // write to a temporary file first so that concurrent readers
+            // never see a partially written entry
+            let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+            std::fs::write(&tmp, serde_json::to_string(entry)?)?;
+            std::fs::rename(tmp, &path)?;
+            Ok(())
+        };
+        if let Err(err) = write() {
+            warn!("could not write to the cache at {}: {err}", path.display());
+        }
+    }
+
+    /// Returns the cached response if it is still fresh,
+    /// or if it is available at all in the offline mode.
+    pub(crate) fn load(&self, url: &str, accept: &str) -> Option<Page> {
+        let entry = self.read(url, accept)?;
+        let age = Duration::from_secs(now().saturating_sub(entry.fetched_at));
+        let fresh = entry.settled || (self.mode != CacheMode::Refresh && age < time_to_live(url));
+        if !fresh && self.mode != CacheMode::Offline {
+            debug!("cached response for {url} has expired");
+            return None;
+        }
+        debug!("serving {url} from the cache");
+        Some(Page {
+            content_type: entry.content_type,
+            body: entry.body,
+        })
+    }
+
+    pub(crate) fn store(&self, url: &str, accept: &str, page: &Page) {
+        if !matches!(self.mode, CacheMode::Enabled | CacheMode::Refresh) {
+            return;
+        }
+        self.write(&CacheEntry {
+            url: url.into(),
+            accept: accept.into(),
+            content_type: page.content_type.clone(),
+            fetched_at: now(),
+            settled: false,
+            body: page.body.clone(),
+        });
+    }
+
+    /// Marks the cached responses for `url` as settled, i.e. they would never
+    /// change on Hydra and thus never expire.
+    pub(crate) fn settle(&self, url: &str) {
+        if !matches!(self.mode, CacheMode::Enabled | CacheMode::Refresh) {
+            return;
+        }
+        for accept in [ACCEPT_HTML, ACCEPT_JSON, ACCEPT_TEXT] {
+            if let Some(entry) = self.read(url, accept).filter(|x| !x.settled) {
+                debug!("marking the cached response for {url} as settled");
+                self.write(&CacheEntry {
+                    settled: true,
+                    ..entry
+                });
+            }
+        }
+    }
+}
+
+/// Time to live of a cached response that has not settled yet.
+fn time_to_live(url: &str) -> Duration {
+    let minutes = |x: u64| Duration::from_secs(x * 60);
+    match url {
+        // new builds and evals show up here all the time
+        x if x.contains("/job/") || x.contains("/jobset/") => minutes(1),
+        // builds and evals change until they are finished, at which point
+        // they are usually settled by the reports
+        x if x.contains("/build/") || x.contains("/eval/") => minutes(5),
+        // the channels advance several times a day, e.g. for `contains`
+        x if x.starts_with("https://channels.nixos.org/") => minutes(1),
+        // anything else, e.g. the channel versions from nixos.org
+        _ => minutes(24 * 60),
+    }
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    let from_env = |var: &str| {
+        std::env::var_os(var)
+            .map(PathBuf::from)
+            .filter(|x| x.is_absolute())
+    };
+    let dir = from_env("XDG_CACHE_HOME").or_else(|| Some(from_env("HOME")?.join(".cache")));
+    if dir.is_none() {
+        warn!("could not determine the cache directory from $XDG_CACHE_HOME or $HOME");
+    }
+    Some(dir?.join(env!("CARGO_PKG_NAME")))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// The 64-bit FNV-1a hash, which is stable across platforms and releases,
+/// unlike the [`std::hash::DefaultHasher`].
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[test]
+fn cache_round_trip() {
+    let dir = std::env::temp_dir().join(format!("hydra-check-test-{}", std::process::id()));
+    let cache = HttpCache {
+        dir: Some(dir.clone()),
+        mode: CacheMode::Enabled,
+    };
+    let url = "https://hydra.nixos.org/job/nixpkgs/unstable/hello.x86_64-linux";
+    let page = Page {
+        content_type: Some("text/html".into()),
+        body: "<html></html>".into(),
+    };
+    assert!(cache.load(url, ACCEPT_HTML).is_none());
+    cache.store(url, ACCEPT_HTML, &page);
+    let cached = cache.load(url, ACCEPT_HTML).unwrap();
+    assert_eq!(cached.body, page.body);
+    assert!(cache.load(url, ACCEPT_JSON).is_none());
+    let refresh = HttpCache {
+        mode: CacheMode::Refresh,
+        ..cache.clone()
+    };
+    assert!(refresh.load(url, ACCEPT_HTML).is_none());
+    cache.settle(url);
+    assert!(cache.read(url, ACCEPT_HTML).is_some_and(|x| x.settled));
+    assert!(refresh.load(url, ACCEPT_HTML).is_some());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn channel_revisions_expire_quickly() {
+    let url = "https://channels.nixos.org/nixos-unstable/git-revision";
+    assert_eq!(time_to_live(url), Duration::from_secs(60));
+    let url = "https://nixos.org/manual/nixpkgs/stable/";
+    assert_eq!(time_to_live(url), Duration::from_secs(24 * 60 * 60));
+}
@@ -0,0 +1,190 @@
+//! Recorded Hydra pages, for bug reports and for the offline tests of the
+//! parsers in `tests/fixtures`.
+//!
+//! With [`FixtureMode::Record`], every fetched page is saved to a fixtures
+//! directory, as a plain file whose path follows its URL, e.g.
+//! `hydra.nixos.org/job/nixpkgs/unstable/hello.x86_64-linux.html`.
+//! With [`FixtureMode::Replay`], the pages are only ever served from there,
+//! without any network access. All of the pages in `tests/fixtures` can thus
+//! be refreshed with:
+//!
+//! ```sh
+//! hydra-check --record tests/fixtures --jobset nixpkgs/unstable hello discord --arch x86_64-linux
+//! hydra-check --record tests/fixtures --jobset nixpkgs/unstable hello --arch aarch64-linux
+//! hydra-check --record tests/fixtures --jobset nixpkgs/unstable --releases
+//! hydra-check --record tests/fixtures --eval 1809808
+//! hydra-check --record tests/fixtures contains 8c4dc69b9732 nixpkgs-unstable nixos-unstable
+//! for backend in html json; do
+//!     hydra-check --record tests/fixtures --backend $backend --build 290062156
+//! done
+//! ```
+//!
+//! followed by `cargo insta test --review` for the snapshots of the parsers.
+//! The pages behind the `Dependency failed` build 289870125, down to the raw
+//! log of the step that failed, are recorded with a client in
+//! [`FixtureMode::Record`] through `HydraClient::failure_cause()` and
+//! `fetch_log_tail()`.
+//! Evaluations are always scraped, so the JSON form of the one behind the
+//! inputs of the build needs to be recorded by hand:
+//!
+//! ```sh
+//! curl -H 'Accept: application/json' https://hydra.nixos.org/eval/1809808 \
+//!     > tests/fixtures/hydra.nixos.org/eval/1809808.json
+//! ```
+
+use log::{debug, warn};
+use std::path::{Path, PathBuf};
+
+use super::{Page, ACCEPT_HTML, ACCEPT_JSON, ACCEPT_TEXT};
+
+/// Usage of a directory of recorded Hydra pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixtureMode {
+    /// Save every fetched page to the directory
+    Record,
+    /// Serve every page from the directory, without network access
+    Replay,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Fixtures {
+    dir: PathBuf,
+    mode: FixtureMode,
+}
+
+impl Fixtures {
+    pub(crate) fn new(mode: FixtureMode, dir: &Path) -> Self {
+        debug!(
+            "using the fixtures in {} in the mode {mode:?}",
+            dir.display()
+        );
+        Self {
+            dir: dir.into(),
+            mode,
+        }
+    }
+
+    pub(crate) fn mode(&self) -> FixtureMode {
+        self.mode
+    }
+
+    fn path(&self, url: &str, extension: &str) -> PathBuf {
+        self.dir.join(fixture_path(url, extension))
+    }
+
+    /// Returns the recorded page for `url`, see [`fixture_extensions`].
+    pub(crate) fn load(&self, url: &str, accept: &str) -> Option<Page> {
+        fixture_extensions(accept).iter().find_map(|extension| {
+            let path = self.path(url, extension);
+            let body = std::fs::read_to_string(&path).ok()?;
+            debug!("serving {url} from {}", path.display());
+            Some(Page {
+                content_type: Some(fixture_content_type(extension).into()),
+                body,
+            })
+        })
+    }
+
+    /// Saves the `page` that has been fetched from `url`.
+    pub(crate) fn store(&self, url: &str, accept: &str, page: &Page) {
+        let content_type = page.content_type.as_deref().unwrap_or(accept);
+        let extension = match content_type {
+            x if x.contains("json") => "json",
+            x if x.contains("html") => "html",
+            _ => "txt",
+        };
+        let path = self.path(url, extension);
+        let write = || -> anyhow::Result<()> {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(&path, &page.body)?;
+            Ok(())
+        };
+        match write() {
+            Ok(()) => debug!("recorded {url} to {}", path.display()),
+            Err(err) => warn!("could not record {url} to {}: {err}", path.display()),
+        }
+    }
+}
+
+/// Path of the fixture for `url` with the given file `extension`, relative
+/// to the fixtures directory.
+pub(crate) fn fixture_path(url: &str, extension: &str) -> PathBuf {
+    let url = url.split_once("://").map_or(url, |x| x.1);
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let sanitize = |text: &str| -> String {
+        text.chars()
+            .map(|x| match x {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '+' | '=' => x,
+                _ => '_',
+            })
+            .collect()
+    };
+    let mut segments: Vec<String> = path
+        .split('/')
+        .filter(|x| !x.is_empty() && *x != "." && *x != "..")
+        .map(sanitize)
+        .collect();
+    if path.ends_with('/') || segments.len() < 2 {
+        segments.push("index".into());
+    }
+    if let Some(last) = segments.last_mut() {
+        if !query.is_empty() {
+            last.push('_');
+            last.push_str(&sanitize(query));
+        }
+        last.push('.');
+        last.push_str(extension);
+    }
+    segments.iter().collect()
+}
+
+/// Extensions of the fixtures that may answer a request with the `Accept`
+/// header, in the order of preference. Hydra responds with HTML to JSON
+/// requests for pages without a JSON form, hence the fallback.
+pub(crate) fn fixture_extensions(accept: &str) -> &'static [&'static str] {
+    match accept {
+        x if x.contains(ACCEPT_JSON) => &["json", "html"],
+        x if x.contains(ACCEPT_TEXT) => &["txt"],
+        _ => &["html"],
+    }
+}
+
+/// Content type of a fixture with the given file `extension`.
+pub(crate) fn fixture_content_type(extension: &str) -> &'static str {
+    match extension {
+        "json" => ACCEPT_JSON,
+        "html" => ACCEPT_HTML,
+        _ => ACCEPT_TEXT,
+    }
+}
+
+/// Client that replays the checked-in fixtures of hydra.nixos.org.
+#[cfg(test)]
+pub(crate) fn replay_client() -> crate::HydraClient {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    crate::HydraClient::new("https://hydra.nixos.org").with_fixtures(FixtureMode::Replay, &dir)
+}
+
+#[test]
+fn fixture_paths() {
+    let fixtures = Fixtures::new(FixtureMode::Replay, Path::new("/fixtures"));
+    let path = |url| fixtures.path(url, "html").display().to_string();
+    assert_eq!(
+        path("https://hydra.nixos.org/job/nixpkgs/unstable/hello.x86_64-linux/all"),
+        "/fixtures/hydra.nixos.org/job/nixpkgs/unstable/hello.x86_64-linux/all.html"
+    );
+    assert_eq!(
+        path("https://hydra.nixos.org/eval/1809808?filter=hello&full=1"),
+        "/fixtures/hydra.nixos.org/eval/1809808_filter=hello_full=1.html"
+    );
+    assert_eq!(
+        path("https://nixos.org/manual/nixpkgs/stable/"),
+        "/fixtures/nixos.org/manual/nixpkgs/stable/index.html"
+    );
+    assert_eq!(
+        path("https://example.org/../../etc/passwd"),
+        "/fixtures/example.org/etc/passwd.html"
+    );
+}
@@ -0,0 +1,29 @@
+use scraper::Html;
+
+use super::{Http, HydraBackend, HydraDocument};
+
+/// Backend that scrapes the HTML pages rendered by Hydra.
+#[derive(Debug, Clone)]
+pub(crate) struct HtmlScraper {
+    http: Http,
+}
+
+impl HtmlScraper {
+    pub(crate) fn new(http: Http) -> Self {
+        Self { http }
+    }
+}
+
+impl HydraBackend for HtmlScraper {
+    fn fetch(&self, url: &str) -> anyhow::Result<HydraDocument> {
+        Ok(HydraDocument::Html(self.fetch_html(url)?))
+    }
+
+    fn fetch_html(&self, url: &str) -> anyhow::Result<Html> {
+        self.http.get_html(url)
+    }
+
+    fn fetch_text(&self, url: &str) -> anyhow::Result<String> {
+        self.http.get_text(url)
+    }
+}
@@ -0,0 +1,132 @@
+use log::debug;
+use scraper::Html;
+
+use super::{Http, HydraBackend, HydraDocument, ACCEPT_JSON};
+use crate::HydraError;
+
+/// Backend that talks to the JSON API of Hydra.
+///
+/// Hydra negotiates the representation with the `Accept` header. Pages
+/// without a JSON form (e.g. the overview of a job) are served as HTML
+/// regardless, in which case the response is handed over to the scraper.
+#[derive(Debug, Clone)]
+pub(crate) struct JsonApi {
+    http: Http,
+}
+
+impl JsonApi {
+    pub(crate) fn new(http: Http) -> Self {
+        Self { http }
+    }
+}
+
+impl HydraBackend for JsonApi {
+    fn fetch(&self, url: &str) -> anyhow::Result<HydraDocument> {
+        let page = self.http.get(url, ACCEPT_JSON)?;
+        let is_json = page.content_type.is_some_and(|x| x.contains("json"));
+        if is_json {
+            let json = serde_json::from_str(&page.body)
+                .map_err(|err| HydraError::parse(url, &err.into()))?;
+            Ok(HydraDocument::Json(json))
+        } else {
+            debug!("no JSON form available for {url}, falling back to the scraper");
+            Ok(HydraDocument::Html(Html::parse_document(&page.body)))
+        }
+    }
+
+    fn fetch_html(&self, url: &str) -> anyhow::Result<Html> {
+        self.http.get_html(url)
+    }
+
+    fn fetch_text(&self, url: &str) -> anyhow::Result<String> {
+        self.http.get_text(url)
+    }
+}
+
+/// Formats a unix timestamp as an ISO 8601 UTC date time, which is what
+/// Hydra shows in the `datetime` attributes of its web pages.
+///
+/// The conversion follows the `civil_from_days` algorithm from
+/// <http://howardhinnant.github.io/date_algorithms.html>.
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+pub(crate) fn iso8601_from_unix(timestamp: u64) -> String {
+    let (days, seconds) = (timestamp / 86400, timestamp % 86400);
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// Converts a UTC date and the `seconds` into the day into a unix
+/// timestamp, which is the inverse of [`iso8601_from_unix`] with the
+/// `days_from_civil` algorithm from the same source.
+pub(crate) fn unix_from_civil(year: u64, month: u64, day: u64, seconds: u64) -> Option<u64> {
+    let year = if month <= 2 {
+        year.checked_sub(1)?
+    } else {
+        year
+    };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day.checked_sub(1)?;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (era * 146_097 + day_of_era).checked_sub(719_468)?;
+    Some(days * 86400 + seconds)
+}
+
+/// Parses an ISO 8601 UTC date time as formatted by [`iso8601_from_unix`],
+/// e.g. `2025-02-22T07:47:19Z`, into a unix timestamp.
+pub(crate) fn unix_from_iso8601(datetime: &str) -> Option<u64> {
+    let (date, time) = datetime.strip_suffix('Z')?.split_once('T')?;
+    let mut date = date.split('-').map(|x| x.parse::<u64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    let mut hms = time.split(':').map(|x| x.parse::<u64>().ok());
+    let (hours, minutes, seconds) = (hms.next()??, hms.next()??, hms.next()??);
+    unix_from_civil(year, month, day, hours * 3600 + minutes * 60 + seconds)
+}
+
+#[test]
+fn format_unix_timestamps() {
+    for (timestamp, datetime) in [
+        (0, "1970-01-01T00:00:00Z"),
+        (951_782_400, "2000-02-29T00:00:00Z"),
+        (1_728_561_599, "2024-10-10T11:59:59Z"),
+    ] {
+        assert_eq!(iso8601_from_unix(timestamp), datetime);
+        assert_eq!(unix_from_iso8601(datetime), Some(timestamp));
+    }
+    assert_eq!(unix_from_iso8601("2024-10-10"), None);
+}
+
+#[test]
+fn replay_json_api() {
+    let client = super::replay_client();
+    let url = "https://hydra.nixos.org/build/290062156";
+    let json_client = super::replay_client().with_backend(crate::BackendKind::Json);
+    let HydraDocument::Json(build) = json_client.backend().fetch(url).unwrap() else {
+        panic!("{url} should be served as JSON");
+    };
+    assert_eq!(build["jobsetevals"][0], 1_809_808);
+    // the same report as the scraper, with the details that have no JSON form
+    let [json, html] = [&json_client, &client].map(|client| {
+        let report = client.build(290_062_156).unwrap();
+        serde_json::to_value(report).unwrap()
+    });
+    assert_eq!(json, html);
+
+    // pages without a JSON form are handed over to the scraper
+    let url = "https://hydra.nixos.org/job/nixpkgs/unstable/hello.x86_64-linux";
+    let document = json_client.backend().fetch(url).unwrap();
+    assert!(matches!(document, HydraDocument::Html(_)));
+}
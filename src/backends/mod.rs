@@ -0,0 +1,281 @@
+//! Pluggable data sources for the Hydra reports.
+//!
+//! A [`HydraBackend`] decides _how_ a Hydra page is requested, and returns
+//! it as a [`HydraDocument`]. The reports then fill the same status structs
+//! from whichever representation they receive:
+//!
+//! - [`HtmlScraper`] fetches the rendered web pages and scrapes them,
+//!   which is the historical behavior of hydra-check.
+//! - [`JsonApi`] asks Hydra for `application/json` and only falls back to
+//!   the scraper for pages that have no JSON form.
+
+mod auth;
+mod cache;
+mod fixtures;
+mod html;
+mod json;
+mod retry;
+
+pub use auth::Auth;
+pub use cache::CacheMode;
+pub use fixtures::FixtureMode;
+pub(crate) use html::HtmlScraper;
+pub(crate) use json::{iso8601_from_unix, unix_from_civil, unix_from_iso8601, JsonApi};
+pub(crate) use retry::DEFAULT_RETRIES;
+
+#[cfg(test)]
+pub(crate) use fixtures::replay_client;
+#[cfg(feature = "mock-hydra")]
+pub(crate) use fixtures::{fixture_content_type, fixture_extensions, fixture_path};
+
+use anyhow::bail;
+use auth::Session;
+use cache::HttpCache;
+use clap::ValueEnum;
+use fixtures::Fixtures;
+use log::warn;
+use reqwest::{
+    blocking::{Client, ClientBuilder, Response},
+    header::{ACCEPT, CONTENT_TYPE},
+    StatusCode,
+};
+use retry::Throttle;
+use scraper::Html;
+use serde_json::Value;
+use std::{fmt::Debug, path::Path, sync::Arc, time::Duration};
+
+use crate::{constants, HydraError};
+
+const ACCEPT_HTML: &str = "text/html";
+const ACCEPT_JSON: &str = "application/json";
+const ACCEPT_TEXT: &str = "text/plain";
+
+/// A fetched Hydra page, in the representation that Hydra responded with.
+pub(crate) enum HydraDocument {
+    Html(Html),
+    Json(Value),
+}
+
+/// Trait for the data sources of hydra-check.
+///
+/// Backends are shared by all the (possibly concurrent) queries of a run,
+/// so that the underlying connection pool can be reused.
+pub(crate) trait HydraBackend: Debug + Send + Sync {
+    /// Fetches the page at `url` in the representation preferred by this
+    /// backend, which may differ from what Hydra eventually responds with.
+    fn fetch(&self, url: &str) -> anyhow::Result<HydraDocument>;
+
+    /// Fetches the web page at `url`, for details that have no other form.
+    fn fetch_html(&self, url: &str) -> anyhow::Result<Html>;
+
+    /// Fetches the plain text at `url`, e.g. a build log.
+    fn fetch_text(&self, url: &str) -> anyhow::Result<String>;
+}
+
+/// Data sources of hydra-check, which is also the command line choice
+/// for `--backend`.
+#[derive(ValueEnum, Debug, Clone, Copy, Default)]
+pub enum BackendKind {
+    /// Scrape the web pages of Hydra
+    #[default]
+    Html,
+    /// Use Hydra's JSON API, falling back to scraping if necessary
+    Json,
+}
+
+impl BackendKind {
+    pub(crate) fn build(self, http: Http) -> Box<dyn HydraBackend> {
+        match self {
+            Self::Html => Box::new(HtmlScraper::new(http)),
+            Self::Json => Box::new(JsonApi::new(http)),
+        }
+    }
+}
+
+/// Body of an HTTP response, along with its content type.
+pub(crate) struct Page {
+    content_type: Option<String>,
+    body: String,
+}
+
+/// HTTP transport shared by the backends, with a connection pool,
+/// an optional on-disk cache, an optional session with Hydra, the
+/// retries and rate limit of the requests, and optional fixtures.
+#[derive(Debug, Clone)]
+pub(crate) struct Http {
+    client: Client,
+    cache: HttpCache,
+    session: Option<Arc<Session>>,
+    throttle: Arc<Throttle>,
+    fixtures: Option<Fixtures>,
+}
+
+fn build_client(builder: ClientBuilder) -> Client {
+    // similar to `Client::new()`, this only fails if the TLS backend
+    // cannot be initialized, or the resolver cannot load the system
+    // configuration, in which case nothing would work anyway
+    builder
+        .timeout(Duration::from_secs(30))
+        .user_agent(constants::APP_USER_AGENT)
+        .build()
+        .expect("the HTTP client should be initialized")
+}
+
+impl Http {
+    pub(crate) fn new(cache_mode: CacheMode) -> Self {
+        Self {
+            client: build_client(Client::builder()),
+            cache: HttpCache::new(cache_mode),
+            session: None,
+            throttle: Arc::default(),
+            fixtures: None,
+        }
+    }
+
+    /// Sets the usage of the on-disk cache, keeping everything else.
+    pub(crate) fn with_cache(self, cache_mode: CacheMode) -> Self {
+        Self {
+            cache: HttpCache::new(cache_mode),
+            ..self
+        }
+    }
+
+    /// Retries the failed GET requests at most `retries` times, and sends
+    /// at most `rate_limit` requests per second, if any.
+    pub(crate) fn with_throttle(self, retries: u32, rate_limit: Option<f64>) -> Self {
+        Self {
+            throttle: Arc::new(Throttle::new(retries, rate_limit)),
+            ..self
+        }
+    }
+
+    /// Records the fetched pages to `dir`, or replays them from there.
+    pub(crate) fn with_fixtures(self, mode: FixtureMode, dir: &Path) -> Self {
+        Self {
+            fixtures: Some(Fixtures::new(mode, dir)),
+            ..self
+        }
+    }
+
+    /// Authenticates the requests to the Hydra instance at `host_url`.
+    pub(crate) fn with_auth(self, host_url: &str, auth: &Auth) -> Self {
+        let Some(session) = Session::new(host_url, auth) else {
+            return Self {
+                client: build_client(Client::builder()),
+                session: None,
+                ..self
+            };
+        };
+        Self {
+            client: build_client(Client::builder().cookie_provider(session.jar())),
+            session: Some(Arc::new(session)),
+            ..self
+        }
+    }
+
+    /// Sends a GET request, with the authentication for the Hydra instance.
+    fn send(&self, url: &str, accept: &str) -> anyhow::Result<Response> {
+        let error = |err| HydraError::from_request(url, err);
+        let request = self.client.get(url).header(ACCEPT, accept);
+        let Some(session) = self.session.as_ref().filter(|x| x.covers(url)) else {
+            return Ok(request.send().map_err(error)?);
+        };
+        session.login(&self.client, false);
+        let response = request.headers(session.headers()).send().map_err(error)?;
+        let denied = [StatusCode::UNAUTHORIZED, StatusCode::FORBIDDEN];
+        if denied.contains(&response.status()) && session.login(&self.client, true) {
+            // the persisted session has expired
+            let request = self.client.get(url).header(ACCEPT, accept);
+            return Ok(request.headers(session.headers()).send().map_err(error)?);
+        }
+        Ok(response)
+    }
+
+    /// Sends a GET request within the rate limit, and retries it as long as
+    /// it fails transiently, e.g. with 503 or a timeout.
+    fn send_with_retries(&self, url: &str, accept: &str) -> anyhow::Result<Response> {
+        let mut attempt = 0;
+        loop {
+            self.throttle.wait();
+            let response = self.send(url, accept);
+            let Some(delay) = self.throttle.retry_delay(attempt, &response) else {
+                return response;
+            };
+            let reason = match &response {
+                Ok(response) => format!("HTTP {}", response.status().as_u16()),
+                Err(err) => format!("{err}"),
+            };
+            warn!(
+                "retrying {url} in {:.1}s after {reason}",
+                delay.as_secs_f64()
+            );
+            std::thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+
+    /// Sends a GET request with the given `Accept` header, unless the
+    /// response is available in the cache, or replayed from the fixtures.
+    pub(crate) fn get(&self, url: &str, accept: &str) -> anyhow::Result<Page> {
+        let Some(fixtures) = &self.fixtures else {
+            return self.fetch(url, accept);
+        };
+        if fixtures.mode() == FixtureMode::Replay {
+            let Some(page) = fixtures.load(url, accept) else {
+                bail!("{url} is not available in the replayed fixtures");
+            };
+            return Ok(page);
+        }
+        let page = self.fetch(url, accept)?;
+        fixtures.store(url, accept, &page);
+        Ok(page)
+    }
+
+    /// Fetches the response from the cache or the network.
+    fn fetch(&self, url: &str, accept: &str) -> anyhow::Result<Page> {
+        if let Some(page) = self.cache.load(url, accept) {
+            return Ok(page);
+        }
+        if self.cache.mode() == CacheMode::Offline {
+            bail!("{url} is not available in the cache for --offline");
+        }
+        let error = |err| HydraError::from_request(url, err);
+        let response = self
+            .send_with_retries(url, accept)?
+            .error_for_status()
+            .map_err(error)?;
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|x| x.to_str().ok())
+            .map(str::to_string);
+        let page = Page {
+            content_type,
+            body: response.text().map_err(error)?,
+        };
+        self.cache.store(url, accept, &page);
+        Ok(page)
+    }
+
+    /// Fetches and parses the web page at `url`.
+    pub(crate) fn get_html(&self, url: &str) -> anyhow::Result<Html> {
+        let page = self.get(url, ACCEPT_HTML)?;
+        Ok(Html::parse_document(&page.body))
+    }
+
+    /// Fetches and parses the JSON at `url`, e.g. from an API other than Hydra.
+    pub(crate) fn get_json(&self, url: &str) -> anyhow::Result<Value> {
+        let page = self.get(url, ACCEPT_JSON)?;
+        serde_json::from_str(&page.body).map_err(|err| HydraError::parse(url, &err.into()).into())
+    }
+
+    /// Fetches the plain text at `url`.
+    pub(crate) fn get_text(&self, url: &str) -> anyhow::Result<String> {
+        Ok(self.get(url, ACCEPT_TEXT)?.body)
+    }
+
+    /// Marks the cached responses for `url` as settled, see [`HttpCache`].
+    pub(crate) fn settle(&self, url: &str) {
+        self.cache.settle(url);
+    }
+}
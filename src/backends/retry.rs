@@ -0,0 +1,173 @@
+//! Retries of the requests that failed transiently, e.g. when Hydra is
+//! overloaded, and a limit on the rate of all the requests.
+//!
+//! Only the GET requests are retried, with an exponential backoff and
+//! a random jitter, unless Hydra asks to wait for a while with
+//! `Retry-After`. The rate limit is shared by all the worker threads.
+
+use reqwest::{blocking::Response, header::RETRY_AFTER, StatusCode};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use super::unix_from_civil;
+use crate::HydraError;
+
+/// Default number of retries of a failed request.
+pub(crate) const DEFAULT_RETRIES: u32 = 3;
+
+/// Delay before the first retry, which doubles with each retry.
+const BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest delay before a retry, even if Hydra asks for more.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Retries and rate limit of the requests of an [`Http`][super::Http].
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    retries: u32,
+    /// Minimum interval between two requests, from the rate limit
+    interval: Option<Duration>,
+    /// Earliest time for the next request
+    next: Mutex<Instant>,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self::new(DEFAULT_RETRIES, None)
+    }
+}
+
+impl Throttle {
+    /// Creates a throttle with the given number of `retries`, and at most
+    /// `rate_limit` requests per second.
+    pub(crate) fn new(retries: u32, rate_limit: Option<f64>) -> Self {
+        Self {
+            retries,
+            interval: rate_limit
+                .filter(|x| x.is_finite() && *x > 0.0)
+                .map(|x| Duration::from_secs_f64(1.0 / x)),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Blocks until the next request is allowed by the rate limit.
+    pub(crate) fn wait(&self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let wait = {
+            let mut next = self
+                .next
+                .lock()
+                .expect("the rate limit should never panic while holding the lock");
+            let now = Instant::now();
+            let wait = next.saturating_duration_since(now);
+            *next = (*next).max(now) + interval;
+            wait
+        };
+        std::thread::sleep(wait);
+    }
+
+    /// Decides whether to retry after the `attempt`-th failure (from 0),
+    /// given the `response` or the error, and returns the delay if so.
+    pub(crate) fn retry_delay(
+        &self,
+        attempt: u32,
+        response: &anyhow::Result<Response>,
+    ) -> Option<Duration> {
+        if attempt >= self.retries {
+            return None;
+        }
+        match response {
+            Ok(response) if is_transient(response.status()) => {
+                Some(retry_after(response).unwrap_or_else(|| backoff(attempt)))
+            }
+            Ok(_) => None,
+            Err(err) => match err.downcast_ref::<HydraError>() {
+                Some(HydraError::Timeout { .. } | HydraError::Network { .. }) => {
+                    Some(backoff(attempt))
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Whether the status is likely to go away by itself, e.g. while Hydra
+/// is restarting or overloaded.
+fn is_transient(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Exponential backoff with a random jitter of up to half of the delay.
+fn backoff(attempt: u32) -> Duration {
+    let delay = BASE_DELAY.saturating_mul(2_u32.saturating_pow(attempt));
+    let jitter = RandomState::new().build_hasher().finish() % 1000;
+    #[allow(clippy::cast_precision_loss)] // the jitter is less than 1000
+    let factor = 1.0 - jitter as f64 / 2000.0;
+    delay.mul_f64(factor).min(MAX_DELAY)
+}
+
+/// Reads the delay of the `Retry-After` header, either in seconds or as
+/// an HTTP date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    let delay = match value.parse::<u64>() {
+        Ok(secs) => Duration::from_secs(secs),
+        Err(_) => {
+            let date = unix_from_http_date(value)?;
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+            Duration::from_secs(date.saturating_sub(now))
+        }
+    };
+    Some(delay.min(MAX_DELAY))
+}
+
+/// Parses an HTTP date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`, into a unix
+/// timestamp.
+fn unix_from_http_date(date: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let [_weekday, day, month, year, time, "GMT"] = date.split_whitespace().collect::<Vec<_>>()[..]
+    else {
+        return None;
+    };
+    let day: u64 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|x| *x == month)? as u64 + 1;
+    let year: u64 = year.parse().ok()?;
+    let mut hms = time.split(':').map(|x| x.parse::<u64>().ok());
+    let (hours, minutes, seconds) = (hms.next()??, hms.next()??, hms.next()??);
+    unix_from_civil(year, month, day, hours * 3600 + minutes * 60 + seconds)
+}
+
+#[test]
+fn retry_delays() {
+    assert_eq!(
+        unix_from_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+        Some(784_111_777)
+    );
+    assert_eq!(
+        unix_from_http_date("Thu, 01 Jan 1970 00:00:00 GMT"),
+        Some(0)
+    );
+    assert_eq!(unix_from_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+    for attempt in 0..8 {
+        let delay = backoff(attempt);
+        let full = BASE_DELAY * 2_u32.pow(attempt);
+        assert!(delay <= full.min(MAX_DELAY));
+        assert!(delay >= (full / 2).min(MAX_DELAY));
+    }
+    assert!(is_transient(StatusCode::SERVICE_UNAVAILABLE));
+    assert!(!is_transient(StatusCode::NOT_FOUND));
+}
@@ -0,0 +1,32 @@
+//! Serves a mock Hydra for integration tests, see [`hydra_check::mock`].
+
+use clap::Parser;
+use hydra_check::mock::MockHydra;
+use std::path::PathBuf;
+
+/// Serve canned Hydra pages on localhost, for testing hydra-check and
+/// other tools against `$HYDRA_CHECK_HOST_URL`
+#[derive(Parser, Debug)]
+#[command(author, version)]
+struct MockHydraCli {
+    /// Address to listen on, where the port 0 picks any free port
+    #[arg(long, default_value = "127.0.0.1:0")]
+    listen: String,
+
+    /// Also serve the pages recorded with `hydra-check --record DIR`
+    #[arg(long, value_name = "DIR")]
+    fixtures: Option<PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = MockHydraCli::parse();
+    let mock = match &args.fixtures {
+        Some(dir) => MockHydra::new().with_fixtures(dir),
+        None => MockHydra::new(),
+    };
+    let server = mock.spawn(&args.listen)?;
+    // the URL is printed on its own line, for scripts to pick it up
+    println!("{}", server.url());
+    server.wait();
+    Ok(())
+}
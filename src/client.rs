@@ -0,0 +1,224 @@
+//! The library interface of hydra-check, see [`HydraClient`].
+
+use std::path::Path;
+
+use crate::{
+    backends::{Auth, BackendKind, CacheMode, FixtureMode, Http, HydraBackend},
+    constants::{self, HYDRA_CHECK_HOST_URL},
+    queries::{BuildReport, EvalReport, JobsetReport, PackageReport},
+    BuildStatus, EvalInput, EvalStatus, Evaluation, FailureCause, ReleaseStatus,
+};
+
+/// A client for a single Hydra instance.
+///
+/// This is the stable library interface of hydra-check. All queries return
+/// the same data structures that are printed by the command line interface
+/// with `--json`. When Hydra shows an alert instead of the requested table
+/// (e.g. for a job that does not exist), or the request fails, the error
+/// is a [`HydraError`][crate::HydraError] that tells the kinds apart.
+///
+/// ```no_run
+/// use hydra_check::HydraClient;
+///
+/// let client = HydraClient::new("https://hydra.nixos.org");
+/// let builds = client.job_builds("nixpkgs/unstable", "hello.x86_64-linux")?;
+/// assert!(builds.first().is_some_and(|build| build.success));
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+#[derive(Debug)]
+pub struct HydraClient {
+    host_url: String,
+    release_url: Option<String>,
+    backend_kind: BackendKind,
+    http: Http,
+    backend: Box<dyn HydraBackend>,
+}
+
+impl Default for HydraClient {
+    /// Creates a client for [`HYDRA_CHECK_HOST_URL`].
+    fn default() -> Self {
+        Self::new(&HYDRA_CHECK_HOST_URL)
+    }
+}
+
+impl HydraClient {
+    /// Creates a client for the Hydra instance at `host_url`,
+    /// e.g. `https://hydra.nixos.org`.
+    #[must_use]
+    pub fn new(host_url: &str) -> Self {
+        let (backend_kind, http) = (BackendKind::default(), Http::new(CacheMode::default()));
+        let host_url: String = host_url.trim().trim_end_matches('/').into();
+        Self {
+            release_url: constants::is_default_host_url(&host_url)
+                .then(|| constants::DEFAULT_RELEASE_URL.into()),
+            host_url,
+            backend_kind,
+            backend: backend_kind.build(http.clone()),
+            http,
+        }
+    }
+
+    /// Sets the data source of the client.
+    #[must_use]
+    pub fn with_backend(self, backend: BackendKind) -> Self {
+        Self {
+            backend_kind: backend,
+            backend: backend.build(self.http.clone()),
+            ..self
+        }
+    }
+
+    /// Sets the usage of the on-disk cache of HTTP responses,
+    /// which is disabled by default.
+    #[must_use]
+    pub fn with_cache(self, cache: CacheMode) -> Self {
+        let http = self.http.clone().with_cache(cache);
+        self.with_http(http)
+    }
+
+    /// Retries the requests that fail transiently (e.g. with 502, 503 or
+    /// a timeout) at most `retries` times, with an exponential backoff
+    /// unless Hydra asks for a `Retry-After`, which is 3 times by default.
+    /// The requests of all the threads are also limited to `rate_limit`
+    /// per second, if any.
+    #[must_use]
+    pub fn with_throttle(self, retries: u32, rate_limit: Option<f64>) -> Self {
+        let http = self.http.clone().with_throttle(retries, rate_limit);
+        self.with_http(http)
+    }
+
+    /// Records all the fetched pages to the directory `dir`, or replays
+    /// them from there without any network access, e.g. for bug reports
+    /// and offline tests.
+    #[must_use]
+    pub fn with_fixtures(self, mode: FixtureMode, dir: &Path) -> Self {
+        let http = self.http.clone().with_fixtures(mode, dir);
+        self.with_http(http)
+    }
+
+    /// Authenticates the requests to the Hydra instance, either with a
+    /// session from its `/login`, or with a bearer token and custom headers.
+    /// The session cookie is persisted across runs, and renewed once Hydra
+    /// denies a request.
+    #[must_use]
+    pub fn with_auth(self, auth: &Auth) -> Self {
+        let http = self.http.clone().with_auth(&self.host_url, auth);
+        self.with_http(http)
+    }
+
+    fn with_http(self, http: Http) -> Self {
+        Self {
+            backend: self.backend_kind.build(http.clone()),
+            http,
+            ..self
+        }
+    }
+
+    /// Sets the template for the URL of the channel releases, which is
+    /// only known for <https://hydra.nixos.org> by default.
+    ///
+    /// The template may refer to the `{channel}` (e.g. `nixos-24.11`),
+    /// its path on <https://releases.nixos.org> as `{channel_path}`
+    /// (e.g. `nixos/24.11`), and the `{name}` of the release test build.
+    #[must_use]
+    pub fn with_release_url(self, template: &str) -> Self {
+        Self {
+            release_url: Some(template.into()),
+            ..self
+        }
+    }
+
+    /// Returns the host URL of the Hydra instance.
+    #[must_use]
+    pub fn host_url(&self) -> &str {
+        &self.host_url
+    }
+
+    pub(crate) fn backend(&self) -> &dyn HydraBackend {
+        &*self.backend
+    }
+
+    /// HTTP transport of the client, for requests to other services.
+    pub(crate) fn http(&self) -> &Http {
+        &self.http
+    }
+
+    /// Marks the cached responses for `url` as settled, for pages that
+    /// would never change again on Hydra.
+    pub(crate) fn settle(&self, url: &str) {
+        self.http.settle(url);
+    }
+
+    pub(crate) fn release_url(&self) -> Option<&str> {
+        self.release_url.as_deref()
+    }
+
+    pub(crate) fn is_default_host(&self) -> bool {
+        constants::is_default_host_url(&self.host_url)
+    }
+
+    /// Fetches the recent builds of a `job` in a `jobset`, e.g.
+    /// `hello.x86_64-linux` in `nixpkgs/unstable`, latest first.
+    pub fn job_builds(&self, jobset: &str, job: &str) -> anyhow::Result<Vec<BuildStatus>> {
+        let report = PackageReport::new(job, jobset, self, false).fetch_and_read(self)?;
+        Ok(report.builds)
+    }
+
+    /// Fetches the recent evaluations of a `jobset`, e.g. `nixpkgs/unstable`,
+    /// latest first.
+    pub fn jobset_evals(&self, jobset: &str) -> anyhow::Result<Vec<EvalStatus>> {
+        let report = JobsetReport::new(jobset, self).fetch_and_read(self)?;
+        Ok(report.evals)
+    }
+
+    /// Fetches the details of the evaluation `id`, with the builds optionally
+    /// restricted to the jobs matching `filter`.
+    pub fn eval(&self, id: u64, filter: Option<&str>) -> anyhow::Result<EvalReport> {
+        let eval = Evaluation::new(id, filter, false);
+        EvalReport::new(eval, self).fetch_and_read(self)
+    }
+
+    /// Fetches the details of the build `build_id`, including its inputs.
+    pub fn build(&self, build_id: u64) -> anyhow::Result<BuildReport> {
+        let url = format!("{}/build/{build_id}", self.host_url);
+        BuildReport::from_url(&url).fetch_and_read_details(self)
+    }
+
+    /// Fetches the inputs of the build `build_id`.
+    pub fn build_inputs(&self, build_id: u64) -> anyhow::Result<Vec<EvalInput>> {
+        let url = format!("{}/build/{build_id}", self.host_url);
+        let report = BuildReport::from_url(&url).fetch_and_read(self)?;
+        Ok(report.inputs)
+    }
+
+    /// Finds the build step that actually failed the build `build_id`,
+    /// e.g. a transitive dependency of a build with `Dependency failed`.
+    pub fn failure_cause(&self, build_id: u64) -> anyhow::Result<Option<FailureCause>> {
+        let url = format!("{}/build/{build_id}", self.host_url);
+        BuildReport::from_url(&url).find_failure_cause(self)
+    }
+
+    /// Fetches the evaluations of a `jobset` along with the builds of its
+    /// release `test_job` (e.g. `tested` for `nixos/unstable`), and joins
+    /// them into the status of potential channel releases.
+    ///
+    /// The `channel` (e.g. `nixos-unstable`) is only used to link to
+    /// the releases, see [`HydraClient::with_release_url`].
+    pub fn releases(
+        &self,
+        jobset: &str,
+        test_job: &str,
+        channel: Option<&str>,
+    ) -> anyhow::Result<Vec<ReleaseStatus>> {
+        let evals = self.jobset_evals(jobset)?;
+        let tests = PackageReport::new(test_job, jobset, self, true).fetch_and_read(self)?;
+        Ok(ReleaseStatus::join(
+            evals,
+            &tests.builds,
+            channel.unwrap_or_default(),
+            jobset,
+            self,
+        ))
+    }
+}
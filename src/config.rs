@@ -0,0 +1,275 @@
+//! Configuration files with defaults and named package lists, read from
+//! `$XDG_CONFIG_HOME/hydra-check/config.toml` and from a project-local
+//! `.hydra-check.toml` in the current directory or any of its parents.
+//!
+//! The project-local file takes precedence over the user's file, while the
+//! command line flags take precedence over both, e.g.:
+//!
+//! ```toml
+//! channel = "24.05"
+//! arch = "aarch64-linux"
+//! host-url = "https://hydra.nixos.org"
+//! format = "json"
+//! retries = 5
+//! rate-limit = 2.0 # requests per second
+//!
+//! [lists]
+//! infra = ["nginx", "postgresql", "prometheus"]
+//! ```
+//!
+//! Other Hydra instances can be added as named hosts, for `--host` or as
+//! the default `host`:
+//!
+//! ```toml
+//! [hosts.nix-community]
+//! url = "https://hydra.nix-community.org"
+//! jobset = "nixpkgs/cuda"
+//! channels.unstable = "nixpkgs/trunk"
+//! architectures = ["x86_64-linux", "aarch64-linux"]
+//! release-url = "https://releases.example.org/{channel}/{name}"
+//!
+//! [hosts.internal]
+//! url = "https://hydra.internal.example.com"
+//! auth.username = "alice" # with the password from ~/.netrc
+//! ```
+//!
+//! The credentials for Hydra's `/login` can also be given by the environment
+//! variables `$HYDRA_CHECK_USERNAME` and `$HYDRA_CHECK_PASSWORD`, while a
+//! bearer token (`auth.token` or `$HYDRA_CHECK_TOKEN`) and custom headers
+//! (`auth.headers`) are meant for instances behind an authenticating proxy.
+
+use anyhow::{anyhow, bail};
+use indexmap::IndexMap;
+use log::{debug, warn};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::{render::OutputFormat, Auth, HydraClient};
+
+/// Name of the project-local configuration file
+const LOCAL_CONFIG: &str = ".hydra-check.toml";
+
+/// Defaults for the command line, and named lists of packages
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct Config {
+    /// Default for `--channel`, unless `--jobset` is given
+    pub(crate) channel: Option<String>,
+    /// Default for `--arch`
+    pub(crate) arch: Option<String>,
+    /// Host URL of the Hydra instance, unless `$HYDRA_CHECK_HOST_URL` is set
+    pub(crate) host_url: Option<String>,
+    /// Name of the default host, which takes precedence over `host-url`
+    pub(crate) host: Option<String>,
+    /// Named Hydra instances for `--host`
+    #[serde(default)]
+    pub(crate) hosts: IndexMap<String, HostProfile>,
+    /// Default output format
+    pub(crate) format: Option<OutputFormat>,
+    /// Default for `--retries`
+    pub(crate) retries: Option<u32>,
+    /// Default for `--rate-limit`
+    pub(crate) rate_limit: Option<f64>,
+    /// Named lists of packages for `--list`
+    #[serde(default)]
+    pub(crate) lists: IndexMap<String, Vec<String>>,
+}
+
+/// Settings of a Hydra instance, selected with `--host`
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct HostProfile {
+    /// Host URL of the Hydra instance
+    pub(crate) url: String,
+    /// Default jobset, unless `--channel` or `--jobset` is given
+    pub(crate) jobset: Option<String>,
+    /// Jobsets of the channels of the instance, e.g. `unstable = "nixpkgs/trunk"`
+    #[serde(default)]
+    pub(crate) channels: IndexMap<String, String>,
+    /// Known architectures, for `--arch all` and the warnings of `--arch`
+    pub(crate) architectures: Option<Vec<String>>,
+    /// Template for the URL of the channel releases,
+    /// see [`HydraClient::with_release_url`]
+    pub(crate) release_url: Option<String>,
+    /// Credentials, a bearer token or custom headers for private instances
+    #[serde(default)]
+    pub(crate) auth: Auth,
+}
+
+impl HostProfile {
+    fn from_url(url: &str) -> Self {
+        Self {
+            url: url.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a client for the Hydra instance, with the credentials from
+    /// the configuration, the environment or netrc.
+    pub(crate) fn client(&self) -> HydraClient {
+        let auth = self.auth.clone().with_defaults(&self.url);
+        let client = HydraClient::new(&self.url).with_auth(&auth);
+        match &self.release_url {
+            Some(template) => client.with_release_url(template),
+            None => client,
+        }
+    }
+}
+
+impl Config {
+    /// Loads and merges all the configuration files that exist.
+    pub(crate) fn load() -> anyhow::Result<Self> {
+        let mut config = Self::default();
+        for path in Self::paths() {
+            if path.is_file() {
+                debug!("loading the configuration from {}", path.display());
+                config = config.merge(Self::read(&path)?);
+            }
+        }
+        Ok(config)
+    }
+
+    fn read(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|err| anyhow!("could not parse {}: {err}", path.display()))
+    }
+
+    /// Paths of the configuration files, in the order of precedence
+    /// from the lowest to the highest.
+    fn paths() -> Vec<PathBuf> {
+        let from_env = |var: &str| {
+            std::env::var_os(var)
+                .map(PathBuf::from)
+                .filter(|x| x.is_absolute())
+        };
+        let mut paths = Vec::new();
+        let dir = from_env("XDG_CONFIG_HOME").or_else(|| Some(from_env("HOME")?.join(".config")));
+        if let Some(dir) = dir {
+            paths.push(dir.join(env!("CARGO_PKG_NAME")).join("config.toml"));
+        } else {
+            warn!("could not determine the config directory from $XDG_CONFIG_HOME or $HOME");
+        }
+        let local = std::env::current_dir().ok().and_then(|dir| {
+            dir.ancestors()
+                .map(|x| x.join(LOCAL_CONFIG))
+                .find(|x| x.is_file())
+        });
+        paths.extend(local);
+        paths
+    }
+
+    /// Resolves `--host` (or the default `host` of the configuration) into
+    /// a profile, either by its name or as a plain URL. There is no profile
+    /// for the default Hydra instance, which can still be overridden by
+    /// `$HYDRA_CHECK_HOST_URL`.
+    pub(crate) fn host_profile(&self, host: Option<&str>) -> anyhow::Result<Option<HostProfile>> {
+        let name = match (host, &self.host) {
+            (Some(name), _) => name,
+            // the environment variable takes precedence over the config
+            _ if std::env::var_os("HYDRA_CHECK_HOST_URL").is_some() => return Ok(None),
+            (None, Some(name)) => name,
+            (None, None) => return Ok(self.host_url.as_deref().map(HostProfile::from_url)),
+        };
+        if let Some(profile) = self.hosts.get(name) {
+            debug!("using --host '{name}' at {}", profile.url);
+            return Ok(Some(profile.clone()));
+        }
+        if name.starts_with("https://") || name.starts_with("http://") {
+            return Ok(Some(HostProfile::from_url(name)));
+        }
+        let names: Vec<_> = self.hosts.keys().collect();
+        bail!("no --host '{name}' in the configuration, available hosts: {names:?}")
+    }
+
+    /// Overrides the settings with those in `other`, and adds its lists
+    /// and hosts.
+    fn merge(self, other: Self) -> Self {
+        let mut lists = self.lists;
+        lists.extend(other.lists);
+        let mut hosts = self.hosts;
+        hosts.extend(other.hosts);
+        Self {
+            channel: other.channel.or(self.channel),
+            arch: other.arch.or(self.arch),
+            host_url: other.host_url.or(self.host_url),
+            host: other.host.or(self.host),
+            format: other.format.or(self.format),
+            retries: other.retries.or(self.retries),
+            rate_limit: other.rate_limit.or(self.rate_limit),
+            lists,
+            hosts,
+        }
+    }
+}
+
+#[test]
+fn merge_configs() {
+    let user: Config = toml::from_str(
+        r#"
+        channel = "unstable"
+        arch = "aarch64-darwin"
+
+        [lists]
+        infra = ["nginx", "postgresql"]
+        desktop = ["firefox"]
+        "#,
+    )
+    .unwrap();
+    let local: Config = toml::from_str(
+        r#"
+        channel = "24.05"
+        format = "json"
+        lists.infra = ["nginx"]
+        "#,
+    )
+    .unwrap();
+    let config = user.merge(local);
+    assert_eq!(config.channel.as_deref(), Some("24.05"));
+    assert_eq!(config.arch.as_deref(), Some("aarch64-darwin"));
+    assert!(matches!(config.format, Some(OutputFormat::Json)));
+    assert_eq!(config.lists["infra"], ["nginx"]);
+    assert_eq!(config.lists["desktop"], ["firefox"]);
+    assert!(toml::from_str::<Config>("chanel = \"unstable\"").is_err());
+}
+
+#[test]
+fn host_profiles() {
+    let config: Config = toml::from_str(
+        r#"
+        host-url = "https://hydra.example.com"
+
+        [hosts.nix-community]
+        url = "https://hydra.nix-community.org"
+        channels.unstable = "nixpkgs/trunk"
+        architectures = ["x86_64-linux", "aarch64-linux"]
+        auth = { username = "alice", headers = { X-Team = "infra" } }
+        "#,
+    )
+    .unwrap();
+    temp_env::with_var("HYDRA_CHECK_HOST_URL", None::<&str>, || {
+        let profile = config.host_profile(Some("nix-community")).unwrap().unwrap();
+        assert_eq!(profile.channels["unstable"], "nixpkgs/trunk");
+        assert_eq!(profile.auth.username.as_deref(), Some("alice"));
+        assert_eq!(profile.auth.headers["X-Team"], "infra");
+        assert_eq!(
+            profile.client().host_url(),
+            "https://hydra.nix-community.org"
+        );
+        assert!(profile.client().release_url().is_none());
+
+        let profile = config.host_profile(None).unwrap().unwrap();
+        assert_eq!(profile.url, "https://hydra.example.com");
+        let profile = config
+            .host_profile(Some("https://hydra.nixos.org/"))
+            .unwrap();
+        assert!(profile.unwrap().client().release_url().is_some());
+        assert!(config.host_profile(Some("nixos")).is_err());
+    });
+    temp_env::with_var(
+        "HYDRA_CHECK_HOST_URL",
+        Some("https://hydra.nixos.org"),
+        || {
+            assert!(config.host_profile(None).unwrap().is_none());
+        },
+    );
+}
@@ -100,8 +100,12 @@ fn get_host_url() -> String {
         })
 }
 
-pub(crate) fn is_default_host_url() -> bool {
-    *HYDRA_CHECK_HOST_URL == HYDRA_CHECK_DEFAULT_HOST_URL
+/// Template for the URL of the channel releases of the default Hydra instance,
+/// see [`HydraClient::with_release_url`][crate::HydraClient::with_release_url].
+pub(crate) const DEFAULT_RELEASE_URL: &str = "https://releases.nixos.org/{channel_path}/{name}";
+
+pub(crate) fn is_default_host_url(host_url: &str) -> bool {
+    host_url == HYDRA_CHECK_DEFAULT_HOST_URL
 }
 
 #[test]
@@ -0,0 +1,274 @@
+//! Errors that scripts may want to tell apart, e.g. a job that does not
+//! exist from a Hydra instance that is down.
+//!
+//! With `--json`, such an error is printed as an `error` object with its
+//! `kind`, either in place of the result of a single package, or in place
+//! of the whole output. Each kind also has its own exit code, see
+//! [`HydraError::exit_code`].
+
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use std::{fmt::Display, time::Duration};
+
+/// Errors of the queries to Hydra, by their kind.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HydraError {
+    /// The job, jobset, evaluation or build does not exist
+    NotFound {
+        /// URL of the page that was requested
+        url: String,
+        /// Explanation from Hydra, if any
+        message: String,
+    },
+    /// The job is not part of the latest evaluation of its jobset, i.e. it
+    /// either does not exist, or could not be evaluated (e.g. for packages
+    /// that are unfree or broken), which Hydra does not tell apart.
+    NotEvaluated {
+        /// URL of the page that was requested
+        url: String,
+        /// Explanation from Hydra
+        message: String,
+    },
+    /// Hydra responded with an HTTP error, e.g. 502 when it is overloaded
+    Http {
+        /// URL of the page that was requested
+        url: String,
+        /// HTTP status code of the response
+        status: u16,
+    },
+    /// The request timed out
+    Timeout {
+        /// URL of the page that was requested
+        url: String,
+    },
+    /// The request failed for any other reason, e.g. the network is down
+    Network {
+        /// URL of the page that was requested
+        url: String,
+        /// Description of the failure
+        message: String,
+    },
+    /// The page could not be parsed, e.g. after a change of Hydra's layout
+    Parse {
+        /// URL of the page that was requested
+        url: String,
+        /// Description of the failure
+        message: String,
+    },
+    /// `--watch` gave up after `--timeout`, with builds or evaluations
+    /// still queued
+    WatchTimeout {
+        /// The `--timeout` that has been reached
+        timeout: Duration,
+    },
+}
+
+impl HydraError {
+    /// Name of the kind of error, e.g. `not-found`, which is the `kind`
+    /// of the `error` object with `--json`.
+    #[must_use]
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::NotFound { .. } => "not-found",
+            Self::NotEvaluated { .. } => "not-evaluated",
+            Self::Http { .. } => "http",
+            Self::Timeout { .. } => "timeout",
+            Self::Network { .. } => "network",
+            Self::Parse { .. } => "parse",
+            Self::WatchTimeout { .. } => "watch-timeout",
+        }
+    }
+
+    /// Exit code of the command line interface for this error. Failed
+    /// builds and any other errors exit with 1, and usage errors with 2.
+    ///
+    /// | kind            | exit code |
+    /// |-----------------|-----------|
+    /// | `not-found`     | 3         |
+    /// | `not-evaluated` | 4         |
+    /// | `http`          | 5         |
+    /// | `timeout`       | 6         |
+    /// | `network`       | 7         |
+    /// | `parse`         | 8         |
+    /// | `watch-timeout` | 124, following the convention of `timeout(1)` |
+    #[must_use]
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::NotFound { .. } => 3,
+            Self::NotEvaluated { .. } => 4,
+            Self::Http { .. } => 5,
+            Self::Timeout { .. } => 6,
+            Self::Network { .. } => 7,
+            Self::Parse { .. } => 8,
+            Self::WatchTimeout { .. } => 124,
+        }
+    }
+
+    /// Whether the requested job, jobset, evaluation or build is missing,
+    /// as opposed to a failure to fetch it.
+    pub(crate) fn is_missing(&self) -> bool {
+        matches!(self, Self::NotFound { .. } | Self::NotEvaluated { .. })
+    }
+
+    /// Classifies a failed request to `url`.
+    pub(crate) fn from_request(url: &str, err: reqwest::Error) -> Self {
+        let url = url.to_string();
+        match err.status() {
+            Some(status) if status == reqwest::StatusCode::NOT_FOUND => Self::NotFound {
+                url,
+                message: "the page does not exist".into(),
+            },
+            Some(status) => Self::Http {
+                url,
+                status: status.as_u16(),
+            },
+            None if err.is_timeout() => Self::Timeout { url },
+            None => Self::Network {
+                // the URL is part of the error already
+                message: ErrorChain(&err.without_url()).to_string(),
+                url,
+            },
+        }
+    }
+
+    /// Classifies the `alert` that Hydra shows in place of the requested
+    /// table at `url`, if any.
+    pub(crate) fn from_alert(url: &str, alert: Option<String>, selector: &str) -> Self {
+        let url = url.to_string();
+        let Some(message) = alert else {
+            let message = format!("could not find '{selector}'");
+            return Self::Parse { url, message };
+        };
+        let lowercase = message.to_lowercase();
+        if ["does not exist", "doesn't exist", "not found"]
+            .iter()
+            .any(|x| lowercase.contains(x))
+        {
+            Self::NotFound { url, message }
+        } else {
+            // e.g. "This job is not a member of the latest evaluation of its
+            // jobset. This means it was removed or had an evaluation error."
+            Self::NotEvaluated { url, message }
+        }
+    }
+
+    /// Wraps a failure to parse the page at `url`.
+    pub(crate) fn parse(url: &str, err: &anyhow::Error) -> Self {
+        Self::Parse {
+            url: url.into(),
+            message: format!("{err:#}"),
+        }
+    }
+}
+
+/// Displays an error along with its sources, like `{:#}` for `anyhow`.
+struct ErrorChain<'a>(&'a dyn std::error::Error);
+
+impl Display for ErrorChain<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)?;
+        let mut source = self.0.source();
+        while let Some(err) = source {
+            write!(f, ": {err}")?;
+            source = err.source();
+        }
+        Ok(())
+    }
+}
+
+impl Display for HydraError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound { url, message } => write!(f, "{message} ({url})"),
+            Self::NotEvaluated { message, .. } => write!(f, "{message}"),
+            Self::Http { url, status } => write!(f, "HTTP {status} from {url}"),
+            Self::Timeout { url } => write!(f, "timed out while fetching {url}"),
+            Self::Network { url, message } => write!(f, "could not fetch {url}: {message}"),
+            Self::Parse { url, message } => write!(f, "could not parse {url}: {message}"),
+            Self::WatchTimeout { timeout } => write!(
+                f,
+                "gave up watching after --timeout {}s, some entries are still queued",
+                timeout.as_secs()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HydraError {}
+
+impl Serialize for HydraError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("kind", self.kind())?;
+        map.serialize_entry("message", &self.to_string())?;
+        match self {
+            Self::NotFound { url, .. }
+            | Self::NotEvaluated { url, .. }
+            | Self::Timeout { url }
+            | Self::Network { url, .. }
+            | Self::Parse { url, .. } => map.serialize_entry("url", url)?,
+            Self::Http { url, status } => {
+                map.serialize_entry("url", url)?;
+                map.serialize_entry("status", status)?;
+            }
+            Self::WatchTimeout { timeout } => map.serialize_entry("timeout", &timeout.as_secs())?,
+        }
+        map.end()
+    }
+}
+
+/// Result of a single query with `--json`, with an `error` object in place
+/// of the value if the query has failed.
+#[derive(Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub(crate) enum JsonResult<T> {
+    Ok(T),
+    Err { error: HydraError },
+}
+
+/// Marks an error that has already been printed, e.g. in place of the
+/// result of a package, so that it only determines the exit code.
+#[derive(Debug)]
+pub(crate) struct Reported;
+
+impl Display for Reported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the error has been reported")
+    }
+}
+
+/// Separates the missing jobs, jobsets, evaluations and builds from the
+/// other errors, which are returned as is.
+pub(crate) fn catch_missing<T>(result: anyhow::Result<T>) -> anyhow::Result<Result<T, HydraError>> {
+    match result {
+        Ok(x) => Ok(Ok(x)),
+        Err(err) => match err.downcast::<HydraError>() {
+            Ok(err) if err.is_missing() => Ok(Err(err)),
+            Ok(err) => Err(err.into()),
+            Err(err) => Err(err),
+        },
+    }
+}
+
+#[test]
+fn error_kinds() {
+    let url = "https://hydra.nixos.org/job/nixpkgs/unstable/hello.x86_64-linux";
+    let alert = "This job is not a member of the latest evaluation of its jobset.";
+    let err = HydraError::from_alert(url, Some(alert.into()), "tbody");
+    assert_eq!(err.kind(), "not-evaluated");
+    assert_eq!(
+        serde_json::to_value(&err).unwrap(),
+        serde_json::json!({ "kind": "not-evaluated", "message": alert, "url": url })
+    );
+    let alert = "Job nixpkgs:unstable:hello doesn't exist.".to_string();
+    assert_eq!(
+        HydraError::from_alert(url, Some(alert), "tbody").exit_code(),
+        3
+    );
+    assert_eq!(HydraError::from_alert(url, None, "tbody").kind(), "parse");
+
+    let result: anyhow::Result<()> = Err(HydraError::Timeout { url: url.into() }.into());
+    assert!(catch_missing(result).is_err());
+    let result: anyhow::Result<()> = Err(err.clone().into());
+    assert_eq!(catch_missing(result).unwrap(), Err(err));
+}
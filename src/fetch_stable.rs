@@ -4,7 +4,10 @@ use once_cell::sync::OnceCell as OnceLock;
 use scraper::Html;
 use serde::Deserialize;
 
-use crate::{SoupFind, TryAttr};
+use crate::{
+    backends::{CacheMode, Http},
+    SoupFind, TryAttr,
+};
 
 /// Static cache for the current stable version of Nixpkgs, set and used
 /// internally by [`NixpkgsChannelVersion::stable()`].
@@ -23,18 +26,15 @@ pub struct NixpkgsChannelVersion {
 }
 
 impl NixpkgsChannelVersion {
-    fn fetch() -> anyhow::Result<Vec<Self>> {
+    fn fetch(http: &Http) -> anyhow::Result<Vec<Self>> {
         debug!("fetching the latest channel version from nixos.org/manual");
-        let document = reqwest::blocking::get("https://nixos.org/manual/nixpkgs/stable/")?
-            .error_for_status()?
-            .text()?;
-        let html = Html::parse_document(&document);
+        let html: Html = http.get_html("https://nixos.org/manual/nixpkgs/stable/")?;
         let channels_spec = html.find("body")?.try_attr("data-nixpkgs-channels")?;
         Ok(serde_json::from_str(channels_spec)?)
     }
 
-    fn fetch_channel(spec: &str) -> anyhow::Result<String> {
-        let channels = Self::fetch()?;
+    fn fetch_channel(spec: &str, http: &Http) -> anyhow::Result<String> {
+        let channels = Self::fetch(http)?;
         for channel in channels.clone() {
             if channel.status == spec {
                 return Ok(channel.version);
@@ -48,7 +48,14 @@ impl NixpkgsChannelVersion {
 
     /// Fetches the current stable version number of Nixpkgs
     pub fn stable() -> anyhow::Result<&'static str> {
-        let version = NIXPKGS_STABLE_VERSION.get_or_try_init(|| Self::fetch_channel("stable"))?;
+        Self::stable_with(&Http::new(CacheMode::default()))
+    }
+
+    /// Fetches the current stable version number of Nixpkgs with the
+    /// given HTTP transport, which may serve it from the cache.
+    pub(crate) fn stable_with(http: &Http) -> anyhow::Result<&'static str> {
+        let version =
+            NIXPKGS_STABLE_VERSION.get_or_try_init(|| Self::fetch_channel("stable", http))?;
         Ok(version.as_str())
     }
 }
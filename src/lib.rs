@@ -2,9 +2,10 @@
 
 //! <div class="warning">
 //!
-//! Currently hydra-check is only provided as a CLI application, _not_ as a library.
-//! We exposed the library documentation here for internal reference.
-//! We do _not_ provide any guarantee about the stability of these interfaces.
+//! hydra-check is primarily provided as a CLI application. The library
+//! interface is limited to [`HydraClient`] and the data structures returned
+//! by it. Everything else is exposed for internal reference only, and we do
+//! _not_ provide any guarantee about the stability of those interfaces.
 //!
 //! </div>
 //!
@@ -12,24 +13,45 @@
 #![doc = include_str!("../README.md")]
 
 mod args;
+mod backends;
+mod client;
+mod config;
+mod error;
 mod fetch_stable;
+mod package_lists;
 mod queries;
+mod render;
 mod structs;
 
 pub mod constants;
+#[cfg(feature = "mock-hydra")]
+pub mod mock;
 pub mod soup;
 
 pub use args::HydraCheckCli;
+pub use backends::{Auth, BackendKind, CacheMode, FixtureMode};
+pub use client::HydraClient;
+pub use error::HydraError;
 pub use fetch_stable::NixpkgsChannelVersion;
+pub use queries::{BuildProduct, BuildReport, EvalInputChanges, EvalReport};
+pub use structs::{
+    BuildStatus, EvalInput, EvalStatus, Evaluation, FailureCause, ReleaseStatus, StatusIcon,
+};
 
 use args::ResolvedArgs;
+use backends::{HydraBackend, HydraDocument};
 use soup::{SoupFind, TryAttr};
-use structs::{BuildStatus, EvalInput, EvalStatus, Evaluation, StatusIcon};
 
 use colored::{ColoredString, Colorize};
 use comfy_table::Table;
 use scraper::{ElementRef, Html};
-use std::time::Duration;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+};
 
 /// Trait for a single `Status` entry from a Hydra report.
 /// This usually corresponds to a single line in the tables from Hydra's
@@ -43,64 +65,57 @@ trait ShowHydraStatus {
 /// such as <https://hydra.nixos.org/job/nixpkgs/unstable/hello.x86_64-linux>.
 trait FetchHydraReport: Clone {
     fn get_url(&self) -> &str;
-    fn fetch_document(&self) -> anyhow::Result<Html> {
-        let document = reqwest::blocking::Client::builder()
-            .timeout(Duration::from_secs(30))
-            .user_agent(constants::APP_USER_AGENT)
-            .build()?
-            .get(self.get_url())
-            .send()?
-            .error_for_status()?
-            .text()?;
-        Ok(Html::parse_document(&document))
+    fn fetch_document(&self, backend: &dyn HydraBackend) -> anyhow::Result<HydraDocument> {
+        backend.fetch(self.get_url())
     }
 
-    fn finish_with_error(self, status: String) -> Self;
-
     /// Checks if the fetched [Html] contains a `tbody` tag (table body).
-    /// If not, returns the alert text. If yes, returns the found element.
-    fn find_tbody<'a>(&self, doc: &'a Html, selector: &str) -> Result<ElementRef<'a>, Self> {
+    /// If not, classifies the alert text into a [`HydraError`].
+    /// If yes, returns the found element.
+    fn find_tbody<'a>(&self, doc: &'a Html, selector: &str) -> Result<ElementRef<'a>, HydraError> {
         let selectors = format!("{selector} tbody");
-        match doc.find(selectors.trim()) {
-            Err(_) => {
-                // either the package was not evaluated (due to being e.g. unfree)
-                // or the package does not exist
-                let status = if let Ok(alert) = doc.find("div.alert") {
-                    alert.text().collect()
-                } else {
-                    format!(
-                        "Unknown Hydra Error with '{}' found at {}",
-                        selectors,
-                        self.get_url()
-                    )
-                };
+        doc.find(selectors.trim()).map_err(|_| {
+            // either the package was not evaluated (due to being e.g. unfree)
+            // or the package does not exist
+            let alert = doc.find("div.alert").ok().map(|alert| {
                 // sanitize the text a little bit
-                let status: Vec<&str> = status.lines().map(str::trim).collect();
-                let status: String = status.join(" ");
-                Err(self.clone().finish_with_error(status))
-            }
-            Ok(tbody) => Ok(tbody),
-        }
+                let status: String = alert.text().collect();
+                let status: Vec<&str> = status.split_whitespace().collect();
+                status.join(" ")
+            });
+            HydraError::from_alert(self.get_url(), alert, selectors.trim())
+        })
     }
 
-    fn format_table<T: ShowHydraStatus>(&self, short: bool, entries: &Vec<T>) -> String {
-        let mut table = Table::new();
-        table.load_preset(comfy_table::presets::NOTHING);
-        // .set_content_arrangement(comfy_table::ContentArrangement::Dynamic);
-        for entry in entries {
-            table.add_row(entry.format_as_vec());
-            if short {
-                break;
-            }
+    /// Wraps a failure to parse the fetched page into a [`HydraError`].
+    fn parse_error(&self, err: &anyhow::Error) -> HydraError {
+        HydraError::parse(self.get_url(), err)
+    }
+
+    fn format_table<T: ShowHydraStatus>(&self, short: bool, entries: &[T]) -> String {
+        format_rows(short, entries)
+    }
+}
+
+/// Formats the `entries` as an aligned table, or only the first entry
+/// if `short` is set.
+fn format_rows<T: ShowHydraStatus>(short: bool, entries: &[T]) -> String {
+    let mut table = Table::new();
+    table.load_preset(comfy_table::presets::NOTHING);
+    // .set_content_arrangement(comfy_table::ContentArrangement::Dynamic);
+    for entry in entries {
+        table.add_row(entry.format_as_vec());
+        if short {
+            break;
         }
-        for (idx, column) in table.column_iter_mut().enumerate() {
-            if idx == 0 {
-                column.set_padding((0, 1));
-            }
-            // column.set_constraint(comfy_table::ColumnConstraint::ContentWidth);
+    }
+    for (idx, column) in table.column_iter_mut().enumerate() {
+        if idx == 0 {
+            column.set_padding((0, 1));
         }
-        table.trim_fmt()
+        // column.set_constraint(comfy_table::ColumnConstraint::ContentWidth);
     }
+    table.trim_fmt()
 }
 
 fn is_skipable_row(row: ElementRef<'_>) -> anyhow::Result<bool> {
@@ -109,6 +124,45 @@ fn is_skipable_row(row: ElementRef<'_>) -> anyhow::Result<bool> {
     Ok(skipable)
 }
 
+/// Calls `fetch` on each of the `items` with at most `jobs` worker threads,
+/// and passes the results to `consume` along with the index of the item.
+/// The results are consumed in the original order of the `items`, as soon as
+/// all of the preceding ones are available.
+fn fetch_in_order<'a, T: Sync, R: Send>(
+    items: &'a [T],
+    jobs: usize,
+    fetch: impl Fn(&'a T) -> R + Sync,
+    mut consume: impl FnMut(usize, R) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let next_item = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, items.len().max(1)) {
+            let (sender, next_item, fetch) = (sender.clone(), &next_item, &fetch);
+            scope.spawn(move || loop {
+                let idx = next_item.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(idx) else {
+                    break; // all items are taken
+                };
+                if sender.send((idx, fetch(item))).is_err() {
+                    break; // the consumer has given up
+                }
+            });
+        }
+        drop(sender);
+        let mut pending = BTreeMap::new();
+        let mut next_result = 0;
+        for (idx, result) in receiver {
+            pending.insert(idx, result);
+            while let Some(result) = pending.remove(&next_result) {
+                consume(next_result, result)?;
+                next_result += 1;
+            }
+        }
+        Ok(())
+    })
+}
+
 fn log_format(
     w: &mut dyn std::io::Write,
     _now: &mut flexi_logger::DeferredNow,
@@ -123,3 +177,21 @@ fn log_format(
     let level = format!("{level}:").to_lowercase().color(color).bold();
     write!(w, "{} {}", level, &record.args())
 }
+
+#[test]
+fn fetch_concurrently_in_order() {
+    let items: Vec<u64> = (0..20).collect();
+    let mut consumed = Vec::new();
+    let fetch = |x: &u64| {
+        // finish the later items first
+        std::thread::sleep(std::time::Duration::from_millis(20 - x));
+        x * 2
+    };
+    fetch_in_order(&items, 4, fetch, |idx, result| {
+        assert_eq!(result, items[idx] * 2);
+        consumed.push(idx);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(consumed, (0..20).collect::<Vec<_>>());
+}
@@ -1,7 +1,14 @@
-use hydra_check::HydraCheckCli;
+use hydra_check::{HydraCheckCli, HydraError};
 
 fn main() -> anyhow::Result<()> {
-    let success = HydraCheckCli::execute()?;
+    let success = match HydraCheckCli::execute() {
+        Err(err) => match err.downcast_ref::<HydraError>() {
+            // the error has been reported by `execute()` already
+            Some(err) => std::process::exit(err.exit_code()),
+            None => return Err(err),
+        },
+        Ok(success) => success,
+    };
     if !success {
         std::process::exit(1);
     }
@@ -0,0 +1,201 @@
+//! A mock Hydra server for integration tests, enabled by the `mock-hydra`
+//! feature, which also provides the `mock-hydra` binary.
+//!
+//! The server answers on localhost with canned job, jobset, evaluation and
+//! build pages of <https://hydra.nixos.org> (see `tests/fixtures`), as HTML
+//! or JSON depending on the `Accept` header, with the links pointing back
+//! to the server itself. Pages recorded with `hydra-check --record DIR` can
+//! be served on top of them with [`MockHydra::with_fixtures`]. Everything
+//! else is answered with 404, like a job that does not exist.
+//!
+//! The command line can then be exercised end-to-end, e.g.:
+//!
+//! ```no_run
+//! use hydra_check::mock::MockHydra;
+//!
+//! let server = MockHydra::new().spawn("127.0.0.1:0")?;
+//! std::process::Command::new("hydra-check")
+//!     .args(["--jobset", "nixpkgs/unstable", "hello", "--arch", "x86_64-linux"])
+//!     .env("HYDRA_CHECK_HOST_URL", server.url())
+//!     .status()?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use log::{debug, warn};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread::JoinHandle,
+};
+
+use crate::backends::{fixture_content_type, fixture_extensions, fixture_path};
+
+/// Host of the canned pages, which is replaced by the URL of the server.
+const CANNED_HOST_URL: &str = "https://hydra.nixos.org";
+
+macro_rules! canned {
+    ($path:literal) => {
+        ($path, include_str!(concat!("../tests/fixtures/", $path)))
+    };
+}
+
+/// Canned pages by their fixture paths, see `tests/fixtures`.
+const CANNED_PAGES: &[(&str, &str)] = &[
+    canned!("hydra.nixos.org/job/nixpkgs/unstable/hello.x86_64-linux.html"),
+    canned!("hydra.nixos.org/job/nixpkgs/unstable/hello.aarch64-linux.html"),
+    canned!("hydra.nixos.org/job/nixpkgs/unstable/discord.x86_64-linux.html"),
+    canned!("hydra.nixos.org/job/nixpkgs/unstable/unstable/all.html"),
+    canned!("hydra.nixos.org/jobset/nixpkgs/unstable/evals.html"),
+    canned!("hydra.nixos.org/eval/1809808.html"),
+    canned!("hydra.nixos.org/eval/1809808.json"),
+    canned!("hydra.nixos.org/build/290062156.html"),
+    canned!("hydra.nixos.org/build/290062156.json"),
+];
+
+/// A mock Hydra server, see the [module documentation](self).
+#[derive(Debug, Clone, Default)]
+pub struct MockHydra {
+    fixtures: Option<PathBuf>,
+}
+
+/// A running [`MockHydra`], which serves until the process exits.
+#[derive(Debug)]
+pub struct MockServer {
+    url: String,
+    handle: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Base URL of the server, e.g. `http://127.0.0.1:34567`, which is meant
+    /// for `$HYDRA_CHECK_HOST_URL`.
+    #[must_use]
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Blocks for as long as the server is running.
+    pub fn wait(self) {
+        let _ = self.handle.join();
+    }
+}
+
+impl MockHydra {
+    /// Creates a server with the canned pages only.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also serves the pages of <https://hydra.nixos.org> recorded with
+    /// `hydra-check --record DIR`, which take precedence over the canned ones.
+    #[must_use]
+    pub fn with_fixtures(self, dir: &Path) -> Self {
+        Self {
+            fixtures: Some(dir.into()),
+        }
+    }
+
+    /// Starts serving on `addr` in a background thread. A port of `0` picks
+    /// any free port, see [`MockServer::url`].
+    pub fn spawn(self, addr: &str) -> std::io::Result<MockServer> {
+        let listener = TcpListener::bind(addr)?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let server = Arc::new(self);
+        let base_url = url.clone();
+        let handle = std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let (server, base_url) = (server.clone(), base_url.clone());
+                let serve = move |stream| {
+                    if let Err(err) = server.respond(&stream, &base_url) {
+                        warn!("could not respond to a request: {err}");
+                    }
+                };
+                match stream {
+                    Ok(stream) => drop(std::thread::spawn(move || serve(stream))),
+                    Err(err) => warn!("could not accept a connection: {err}"),
+                }
+            }
+        });
+        Ok(MockServer { url, handle })
+    }
+
+    /// Finds the page at `target` (e.g. `/eval/1809808`) for `accept`,
+    /// returning its body and content type.
+    fn page(&self, target: &str, accept: &str) -> Option<(String, &'static str)> {
+        let url = format!("{CANNED_HOST_URL}{target}");
+        fixture_extensions(accept).iter().find_map(|extension| {
+            let path = fixture_path(&url, extension);
+            let recorded = self
+                .fixtures
+                .as_ref()
+                .and_then(|dir| std::fs::read_to_string(dir.join(&path)).ok());
+            let body = recorded.or_else(|| {
+                let path = path.to_str()?.replace('\\', "/");
+                CANNED_PAGES
+                    .iter()
+                    .find(|(canned, _)| *canned == path)
+                    .map(|(_, body)| (*body).to_string())
+            })?;
+            Some((body, fixture_content_type(extension)))
+        })
+    }
+
+    fn respond(&self, stream: &TcpStream, base_url: &str) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut accept = String::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("accept") {
+                    accept = value.trim().to_string();
+                }
+            }
+        }
+        let mut parts = request_line.split_whitespace();
+        let (method, target) = (parts.next().unwrap_or_default(), parts.next());
+        let target = target.unwrap_or("/").split('#').next().unwrap_or("/");
+        debug!("mock request: {method} {target} (Accept: {accept})");
+        let (status, body, content_type) = match (method, self.page(target, &accept)) {
+            ("GET", Some((body, content_type))) => (
+                "200 OK",
+                body.replace(CANNED_HOST_URL, base_url),
+                content_type,
+            ),
+            ("GET", None) => ("404 Not Found", "Page not found.\n".into(), "text/plain"),
+            _ => (
+                "405 Method Not Allowed",
+                "Only GET is supported.\n".into(),
+                "text/plain",
+            ),
+        };
+        let mut stream = stream;
+        write!(
+            stream,
+            "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )?;
+        stream.flush()
+    }
+}
+
+#[test]
+fn canned_pages() {
+    let mock = MockHydra::new();
+    let (body, content_type) = mock.page("/eval/1809808", "application/json").unwrap();
+    assert_eq!(content_type, "application/json");
+    assert!(body.contains("jobsetevalinputs"));
+    let (_, content_type) = mock
+        .page("/jobset/nixpkgs/unstable/evals", "application/json")
+        .unwrap();
+    assert_eq!(content_type, "text/html");
+    assert!(mock
+        .page("/job/nixpkgs/unstable/nonexistent", "*/*")
+        .is_none());
+}
@@ -0,0 +1,153 @@
+//! Lists of packages from files, stdin, the current NixOS system or a
+//! `nix profile`, in addition to the packages on the command line.
+//!
+//! The attribute names of the system and the profile are only guessed,
+//! since the store paths do not record the attributes they are built from.
+
+use std::{collections::BTreeSet, io::Read, path::Path, process::Command};
+
+use anyhow::{anyhow, bail};
+use log::{debug, warn};
+use serde_json::Value;
+
+/// Profile of the packages of the current NixOS system
+const CURRENT_SYSTEM: &str = "/run/current-system/sw";
+
+/// Reads attribute names, one per line, ignoring `#` comments.
+pub(crate) fn read_list(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|line| line.split_once('#').map_or(line, |(x, _)| x).trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Reads the attribute names from the file at `path`, or from stdin
+/// if the path is `-`.
+pub(crate) fn from_file(path: &Path) -> anyhow::Result<Vec<String>> {
+    let text = if path == Path::new("-") {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text)?;
+        text
+    } else {
+        std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("could not read {}: {err}", path.display()))?
+    };
+    Ok(read_list(&text))
+}
+
+/// Guesses the attribute name of a store path from the name of the
+/// derivation, similar to `builtins.parseDrvName`, e.g. `hello` from
+/// `/nix/store/<hash>-hello-2.12.1`.
+fn pname_from_store_path(path: &str) -> Option<String> {
+    let name = path.trim_end_matches('/').rsplit('/').next()?;
+    let (_hash, name) = name.split_once('-')?;
+    if Path::new(name).extension().is_some_and(|x| x == "drv") {
+        return None;
+    }
+    // the version starts at the first dash that is followed by a non-letter
+    let version = name
+        .match_indices('-')
+        .find(|(idx, _)| !name[idx + 1..].starts_with(|c: char| c.is_alphabetic()));
+    let pname = match version {
+        Some((idx, _)) => &name[..idx],
+        None => name,
+    };
+    Some(pname.into())
+}
+
+/// Guesses the attribute names of the packages of the current NixOS system,
+/// from the references of `/run/current-system/sw`.
+pub(crate) fn from_system() -> anyhow::Result<Vec<String>> {
+    if !Path::new(CURRENT_SYSTEM).exists() {
+        bail!("{CURRENT_SYSTEM} does not exist, --from-system only works on NixOS");
+    }
+    let output = Command::new("nix-store")
+        .args(["--query", "--references", CURRENT_SYSTEM])
+        .output()
+        .map_err(|err| anyhow!("could not run nix-store for --from-system: {err}"))?;
+    if !output.status.success() {
+        bail!(
+            "could not query the packages of {CURRENT_SYSTEM}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let paths = String::from_utf8(output.stdout)?;
+    let packages: BTreeSet<_> = paths.lines().filter_map(pname_from_store_path).collect();
+    debug!("--from-system resolves to: {packages:?}");
+    Ok(packages.into_iter().collect())
+}
+
+/// Reads the attribute names of the nixpkgs packages in the manifest of
+/// a `nix profile`, e.g. `hello` from `legacyPackages.x86_64-linux.hello`.
+fn attrs_from_manifest(manifest: &Value) -> anyhow::Result<Vec<String>> {
+    // the elements are a list in version 2 of the manifest, and
+    // a map from the names of the elements in version 3
+    let elements: Vec<_> = match &manifest["elements"] {
+        Value::Array(x) => x.iter().collect(),
+        Value::Object(x) => x.values().collect(),
+        _ => bail!("could not find the elements of the profile in: {manifest}"),
+    };
+    let mut attrs = Vec::new();
+    for element in elements {
+        let Some(attr_path) = element["attrPath"].as_str() else {
+            continue; // e.g. a store path that is installed directly
+        };
+        match attr_path.splitn(3, '.').collect::<Vec<_>>().as_slice() {
+            ["legacyPackages", _system, attr] => attrs.push((*attr).to_string()),
+            _ => warn!("skipping '{attr_path}' in the profile, which is not from nixpkgs"),
+        }
+    }
+    Ok(attrs)
+}
+
+/// Reads the attribute names of the nixpkgs packages installed with
+/// `nix profile` in the `profile`, e.g. `~/.nix-profile`.
+pub(crate) fn from_profile(profile: &Path) -> anyhow::Result<Vec<String>> {
+    let path = profile.join("manifest.json");
+    let manifest = std::fs::read_to_string(&path).map_err(|err| {
+        anyhow!(
+            "could not read {}, which is only available for `nix profile`: {err}",
+            path.display()
+        )
+    })?;
+    attrs_from_manifest(&serde_json::from_str(&manifest)?)
+}
+
+#[test]
+fn read_package_lists() {
+    let text = "# packages that we run\nhello\n\n  coreutils  # the basics\n#gimp\n";
+    assert_eq!(read_list(text), ["hello", "coreutils"]);
+
+    for (path, pname) in [
+        (
+            "/nix/store/p4pclmv1gyja5kzc26npqpia1qqxrf0l-hello-2.12.1",
+            "hello",
+        ),
+        (
+            "/nix/store/p4pclmv1gyja5kzc26npqpia1qqxrf0l-hello-2.12.1-man",
+            "hello",
+        ),
+        (
+            "/nix/store/p4pclmv1gyja5kzc26npqpia1qqxrf0l-nixos-rebuild",
+            "nixos-rebuild",
+        ),
+        (
+            "/nix/store/p4pclmv1gyja5kzc26npqpia1qqxrf0l-gnome-shell-47.0",
+            "gnome-shell",
+        ),
+    ] {
+        assert_eq!(pname_from_store_path(path).as_deref(), Some(pname));
+    }
+
+    let manifest = serde_json::json!({
+        "version": 3,
+        "elements": {
+            "hello": { "attrPath": "legacyPackages.x86_64-linux.hello" },
+            "home-manager": { "attrPath": "packages.x86_64-linux.default" },
+            "python": { "attrPath": "legacyPackages.x86_64-linux.python3Packages.numpy" },
+        }
+    });
+    let attrs = attrs_from_manifest(&manifest).unwrap();
+    assert_eq!(attrs, ["hello", "python3Packages.numpy"]);
+}
@@ -0,0 +1,340 @@
+//! A module that bisects the build history of a package for the first
+//! failing evaluation, for the `bisect` subcommand.
+//!
+//! The job page only tells us _which_ builds failed, so the builds are then
+//! mapped to the evaluations of the jobset, whose inputs and input changes
+//! give the range of nixpkgs revisions that introduced the failure.
+
+use anyhow::bail;
+use colored::Colorize;
+use log::{info, warn};
+use scraper::Html;
+use serde::Serialize;
+use serde_with::skip_serializing_none;
+
+use super::{EvalInputChanges, EvalReport, JobsetReport, PackageReport};
+#[cfg(test)]
+use insta::assert_snapshot;
+
+use crate::{
+    render::Renderer, BuildStatus, EvalInput, EvalStatus, Evaluation, FetchHydraReport,
+    HydraClient, ResolvedArgs, SoupFind, StatusIcon,
+};
+
+#[skip_serializing_none]
+#[derive(Serialize, Debug, Clone, Default)]
+/// Result of bisecting the build history of a package
+pub(crate) struct BisectReport {
+    /// Full name of the job, e.g. `hello.x86_64-linux`
+    package: String,
+    jobset: String,
+    /// The latest successful build before the failures
+    last_success: Option<BuildStatus>,
+    /// The oldest failing build after the last success
+    first_failure: Option<BuildStatus>,
+    /// The latest evaluation of the jobset with the last success
+    last_good_eval: Option<u64>,
+    /// The first evaluation of the jobset with the first failure
+    first_bad_eval: Option<u64>,
+    /// Nixpkgs revisions of the two evaluations
+    revs: Option<(String, String)>,
+    /// Changes of the nixpkgs input in the first bad evaluation,
+    /// compared to the evaluation right before it
+    changes: Option<EvalInputChanges>,
+    /// URL to compare the revisions on GitHub
+    compare_url: Option<String>,
+    /// Recent evaluations of the jobset in between, if any
+    evals: Vec<EvalStatus>,
+}
+
+/// Finds the input for nixpkgs, or the first input with a revision.
+pub(super) fn nixpkgs_input(inputs: &[EvalInput]) -> Option<&EvalInput> {
+    let with_revision = || inputs.iter().find(|x| x.revision.is_some());
+    inputs
+        .iter()
+        .find(|x| x.name.as_deref() == Some("nixpkgs") && x.revision.is_some())
+        .or_else(with_revision)
+}
+
+/// Fetches the IDs of the evaluations of `jobset` that contain the build
+/// at `build_url`, in ascending order.
+pub(super) fn fetch_build_evals(
+    client: &HydraClient,
+    build_url: &str,
+    jobset: &str,
+) -> anyhow::Result<Vec<u64>> {
+    //
+    // e.g. https://hydra.nixos.org/build/290062156/evals, which lists the
+    // evals of all the jobsets that share the build, with a `Jobset` column
+    //
+    let url = format!("{build_url}/evals");
+    let doc = client.backend().fetch_html(&url)?;
+    let evals = read_build_evals(&doc, jobset);
+    if evals.is_empty() {
+        bail!("could not find the evaluations of jobset '{jobset}' at {url}");
+    }
+    Ok(evals)
+}
+
+/// Reads the IDs of the evaluations of `jobset` from the `/build/<id>/evals`
+/// page, in ascending order.
+fn read_build_evals(doc: &Html, jobset: &str) -> Vec<u64> {
+    let mut evals = Vec::new();
+    for row in doc.find_all("tbody tr") {
+        let Ok(eval) = row.find("a[href*='/eval/']") else {
+            continue;
+        };
+        let other_jobset = row
+            .find("a[href*='/jobset/']")
+            .ok()
+            .and_then(|x| x.attr("href"))
+            .is_some_and(|x| {
+                !x.trim_end_matches('/')
+                    .ends_with(&format!("/jobset/{jobset}"))
+            });
+        if other_jobset {
+            continue;
+        }
+        let id = eval.attr("href").and_then(|x| x.rsplit('/').next());
+        if let Some(id) = id.and_then(|x| x.parse().ok()) {
+            evals.push(id);
+        }
+    }
+    evals.sort_unstable();
+    evals
+}
+
+/// The builds of a job that have finished within an evaluation, i.e.
+/// without the queued and cancelled ones.
+fn finished_builds(builds: Vec<BuildStatus>) -> Vec<BuildStatus> {
+    builds
+        .into_iter()
+        .filter(|x| x.evals && !matches!(x.icon, StatusIcon::Queued | StatusIcon::Cancelled))
+        .collect()
+}
+
+/// Finds the last success among the `builds`, latest first, and the oldest
+/// failure after it, unless the latest build has succeeded or there is no
+/// success at all.
+fn find_regression(builds: &[BuildStatus]) -> Option<(&BuildStatus, &BuildStatus)> {
+    let idx = builds.iter().position(|x| x.success)?;
+    Some((&builds[idx], builds.get(idx.checked_sub(1)?)?))
+}
+
+/// Finds the range of revisions between the `good` and the `bad` input,
+/// preferring the input `changes` of the bad evaluation when the two
+/// evaluations are adjacent, along with the URL to compare them on GitHub.
+fn revision_range(
+    good: Option<&EvalInput>,
+    bad: Option<&EvalInput>,
+    changes: Option<&EvalInputChanges>,
+) -> (Option<(String, String)>, Option<String>) {
+    let good_rev = good.and_then(|x| x.revision.clone());
+    let revs = match changes.and_then(|x| x.revs.clone()) {
+        Some((from, to)) if good_rev.is_none() || good_rev.as_ref() == Some(&from) => {
+            Some((from, to))
+        }
+        _ => good_rev.zip(bad.and_then(|x| x.revision.clone())),
+    };
+    let compare_url = revs.as_ref().and_then(|(from, to)| {
+        let repo = bad?.value.as_deref()?;
+        let repo = repo.strip_suffix(".git").unwrap_or(repo);
+        repo.contains("github.com")
+            .then(|| format!("{repo}/compare/{from}...{to}"))
+    });
+    (revs, compare_url)
+}
+
+impl BisectReport {
+    fn fetch(client: &HydraClient, package: &str, jobset: &str) -> anyhow::Result<Self> {
+        let stat = Self {
+            package: package.into(),
+            jobset: jobset.into(),
+            ..Default::default()
+        };
+        // all the recent builds of the job, latest first
+        let builds = PackageReport::new(package, jobset, client, true).fetch_and_read(client)?;
+        let builds = finished_builds(builds.builds);
+        let Some(latest) = builds.first() else {
+            bail!("no finished builds found for '{package}' on jobset '{jobset}'");
+        };
+        if latest.success {
+            info!("the latest build of '{package}' succeeded, so there is nothing to bisect");
+            return Ok(Self {
+                last_success: Some(latest.clone()),
+                ..stat
+            });
+        }
+        let Some((last_success, first_failure)) = find_regression(&builds) else {
+            warn!("no success found in the recent builds of '{package}', try an older --jobset");
+            return Ok(Self {
+                first_failure: builds.last().cloned(),
+                ..stat
+            });
+        };
+        let eval_ids = |build: &BuildStatus| match &build.build_url {
+            Some(url) => fetch_build_evals(client, url, jobset),
+            None => bail!("no build URL found for {build:?}"),
+        };
+        let last_good_eval = eval_ids(last_success)?.last().copied();
+        let first_bad_eval = eval_ids(first_failure)?.first().copied();
+        let [good, bad] = [last_good_eval, first_bad_eval].map(|id| {
+            let id = id?;
+            let eval = Evaluation::new(id, Some(package), false);
+            let report = EvalReport::new(eval, client).fetch_and_read(client);
+            report
+                .inspect_err(|err| warn!("could not fetch the evaluation {id}: {err}"))
+                .ok()
+        });
+        let input = |report: &Option<EvalReport>| nixpkgs_input(&report.as_ref()?.inputs).cloned();
+        let (good_input, bad_input) = (input(&good), input(&bad));
+        let changes = bad.and_then(|report| {
+            let name = bad_input.as_ref()?.name.clone();
+            report
+                .changes
+                .into_iter()
+                .find(|x| Some(&x.input) == name.as_ref())
+        });
+        let (revs, compare_url) =
+            revision_range(good_input.as_ref(), bad_input.as_ref(), changes.as_ref());
+        let evals = match (last_good_eval, first_bad_eval) {
+            (Some(good), Some(bad)) => JobsetReport::new(jobset, client)
+                .fetch_and_read(client)?
+                .evals
+                .into_iter()
+                .filter(|x| x.id.is_some_and(|id| good <= id && id <= bad))
+                .collect(),
+            _ => vec![],
+        };
+        Ok(Self {
+            last_success: Some(last_success.clone()),
+            first_failure: Some(first_failure.clone()),
+            last_good_eval,
+            first_bad_eval,
+            revs,
+            changes,
+            compare_url,
+            evals,
+            ..stat
+        })
+    }
+}
+
+impl ResolvedArgs {
+    pub(crate) fn fetch_and_print_bisect(&self, package: &str) -> anyhow::Result<bool> {
+        let stat = PackageReport::new(package, &self.jobset, &self.client, true);
+        if self.url {
+            println!("{}", stat.get_url());
+            return Ok(true);
+        }
+        if self.format.is_table() {
+            // print title first, then fetch
+            println!(
+                "Bisecting {} on jobset {} {}",
+                package.bold(),
+                self.jobset.bold(),
+                format!("@ {}", stat.get_url()).dimmed(),
+            );
+        }
+        let report = BisectReport::fetch(&self.client, package, &self.jobset)?;
+        let found = report.revs.is_some();
+        if !self.format.is_table() {
+            let mut renderer = Renderer::new(self.format);
+            renderer.push(&[("package", package)], &report)?;
+            renderer.finish(&report)?;
+            return Ok(found);
+        }
+        let builds: Vec<_> = [&report.first_failure, &report.last_success]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect();
+        println!("{}", stat.format_table(false, &builds));
+        let eval_url = |id: u64| format!("{}/eval/{id}", self.client.host_url());
+        let mut lines = Vec::new();
+        if let Some(id) = report.last_good_eval {
+            lines.push(("last_good_eval", eval_url(id)));
+        }
+        if let Some(id) = report.first_bad_eval {
+            lines.push(("first_bad_eval", eval_url(id)));
+        }
+        if let Some((from, to)) = &report.revs {
+            lines.push(("revs", format!("{from} -> {to}")));
+        }
+        if let Some(url) = &report.compare_url {
+            lines.push(("compare", url.clone()));
+        }
+        if !lines.is_empty() {
+            println!();
+        }
+        for (key, value) in lines {
+            println!("{}: {value}", key.bold());
+        }
+        if report.evals.len() > 2 {
+            println!("\n{}", "Evaluations in between:".bold());
+            println!("{}", stat.format_table(false, &report.evals));
+        }
+        Ok(found)
+    }
+}
+
+#[test]
+fn bisect_build_history() {
+    let client = crate::backends::replay_client();
+    let stat = PackageReport::new("hello.x86_64-linux", "nixpkgs/unstable", &client, false)
+        .fetch_and_read(&client)
+        .unwrap();
+    // without the queued build
+    let builds = finished_builds(stat.builds);
+    let ids: Vec<_> = builds
+        .iter()
+        .filter_map(|x| x.build_id.as_deref())
+        .collect();
+    assert_eq!(ids, ["290062156", "289870122", "289504237"]);
+    // the latest build has succeeded
+    assert!(find_regression(&builds).is_none());
+    let (last_success, first_failure) = find_regression(&builds[1..]).unwrap();
+    assert_eq!(last_success.build_id.as_deref(), Some("289504237"));
+    assert_eq!(first_failure.build_id.as_deref(), Some("289870122"));
+    assert!(find_regression(&builds[1..2]).is_none());
+
+    let doc = Html::parse_document(
+        r#"<table><tbody>
+        <tr><td><a href="https://hydra.nixos.org/eval/1809808">1809808</a></td>
+            <td><a href="https://hydra.nixos.org/jobset/nixpkgs/unstable">nixpkgs:unstable</a></td></tr>
+        <tr><td><a href="https://hydra.nixos.org/eval/1809810">1809810</a></td>
+            <td><a href="https://hydra.nixos.org/jobset/nixpkgs/staging-next">nixpkgs:staging-next</a></td></tr>
+        <tr><td><a href="https://hydra.nixos.org/eval/1809801">1809801</a></td>
+            <td><a href="https://hydra.nixos.org/jobset/nixpkgs/unstable/">nixpkgs:unstable</a></td></tr>
+        </tbody></table>"#,
+    );
+    assert_eq!(
+        read_build_evals(&doc, "nixpkgs/unstable"),
+        [1_809_801, 1_809_808]
+    );
+}
+
+#[test]
+fn bisect_revision_range() {
+    let client = crate::backends::replay_client();
+    // the first bad evaluation, whose nixpkgs changed from 8c4dc69b9732
+    let bad = client.eval(1_809_808, None).unwrap();
+    let bad_input = nixpkgs_input(&bad.inputs);
+    let changes = bad.changes.first();
+    let good = |revision: &str| EvalInput {
+        revision: Some(revision.into()),
+        ..Default::default()
+    };
+
+    // the last good evaluation is right before the bad one
+    let adjacent = good("8c4dc69b9732f6bbe826b5fbb32184987520ff26");
+    let (revs, compare_url) = revision_range(Some(&adjacent), bad_input, changes);
+    assert_eq!(revs, changes.and_then(|x| x.revs.clone()));
+    assert_snapshot!(compare_url.unwrap(), @"https://github.com/nixos/nixpkgs/compare/8c4dc69b9732f6bbe826b5fbb32184987520ff26...1e9e641a3fc1b22fbdb823a99d8ff96692cc4fba");
+
+    // there are more evaluations in between
+    let earlier = good("0196c0175e9191c474c26ab5548db27ef5d34b05");
+    let (revs, compare_url) = revision_range(Some(&earlier), bad_input, changes);
+    assert_eq!(revs.unwrap().0, "0196c0175e9191c474c26ab5548db27ef5d34b05");
+    assert_snapshot!(compare_url.unwrap(), @"https://github.com/nixos/nixpkgs/compare/0196c0175e9191c474c26ab5548db27ef5d34b05...1e9e641a3fc1b22fbdb823a99d8ff96692cc4fba");
+}
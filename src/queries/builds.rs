@@ -2,54 +2,709 @@
 //! from urls such as <https://hydra.nixos.org/build/290062156>.
 //!
 //! This module is adapted from the `evals` module as the two are similar
-//! in structure. The module is currently only used by the `builds` module,
-//! hence the relevant interfaces are marked as `pub(super)`.
+//! in structure. Besides `--build`, the module is used by the `packages`
+//! module for the inputs of the latest finished build and the causes of
+//! failures.
 
+use anyhow::bail;
+use colored::Colorize;
+use indexmap::IndexMap;
+use regex::Regex;
+use scraper::{ElementRef, Html};
 use serde::Serialize;
+use serde_json::Value;
+use serde_with::skip_serializing_none;
+use std::fmt::Display;
 
-use crate::{EvalInput, FetchHydraReport, StatusIcon};
+#[cfg(test)]
+use insta::assert_snapshot;
+
+use crate::{
+    backends::{iso8601_from_unix, HydraDocument},
+    fetch_in_order,
+    render::Renderer,
+    BuildStatus, EvalInput, FailureCause, FetchHydraReport, HydraClient, HydraError, ResolvedArgs,
+    SoupFind, StatusIcon, TryAttr,
+};
+
+/// Maximum number of builds to follow for a failure that is propagated
+/// from another build, which is usually one or two in practice.
+const MAX_PROPAGATION_DEPTH: usize = 4;
+
+#[non_exhaustive]
+#[skip_serializing_none]
+#[derive(Serialize, Clone, Debug, Default)]
+/// A product of a build, e.g. a file for download
+pub struct BuildProduct {
+    /// Name of the product, e.g. `nixos-minimal-24.05.iso`
+    pub name: Option<String>,
+    /// Type of the product, e.g. `file`
+    #[serde(rename = "type")]
+    pub product_type: Option<String>,
+    /// Store path of the product
+    pub path: Option<String>,
+    /// Size of the product in bytes
+    pub size: Option<u64>,
+    /// SHA-256 hash of the product
+    pub sha256: Option<String>,
+}
+
+impl Display for BuildProduct {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let json = serde_json::to_value(self).expect("BuildProduct should be serialized into json");
+        let strings: Vec<_> = ["name", "type", "path", "size", "sha256"]
+            .iter()
+            .filter_map(|key| match &json[key] {
+                Value::Null => None,
+                // unquote the string:
+                Value::String(value) => {
+                    let key = match *key {
+                        "name" => "product",
+                        k => k,
+                    };
+                    Some(format!("{}: {}", key.bold(), value))
+                }
+                value => Some(format!("{}: {}", key.bold(), value)),
+            })
+            .collect();
+        write!(f, "{}", strings.join("\n"))
+    }
+}
 
 #[non_exhaustive]
-#[derive(Serialize, Clone)]
-pub(super) struct BuildReport {
-    url: String,
-    pub(super) inputs: Vec<EvalInput>,
+#[skip_serializing_none]
+#[derive(Serialize, Clone, Debug, Default)]
+/// Details of a single build, e.g. from <https://hydra.nixos.org/build/290062156>
+pub struct BuildReport {
+    /// URL of the build on Hydra
+    pub url: String,
+    /// Status of the build
+    #[serde(flatten)]
+    pub build: BuildStatus,
+    /// Date and time when the build started, in ISO 8601
+    pub started: Option<String>,
+    /// Date and time when the build stopped, in ISO 8601
+    pub stopped: Option<String>,
+    /// Duration of the build in seconds
+    pub duration: Option<u64>,
+    /// Machine that performed the build
+    pub machine: Option<String>,
+    /// Store path of the derivation
+    pub drv_path: Option<String>,
+    /// Store paths of the outputs
+    pub outputs: Vec<String>,
+    /// Closure size of the outputs as shown by Hydra, e.g. `34.19 MiB`
+    pub closure_size: Option<String>,
+    /// Products of the build, e.g. release tarballs
+    pub products: Vec<BuildProduct>,
+    /// Inputs of the build
+    pub inputs: Vec<EvalInput>,
 }
 
 impl FetchHydraReport for BuildReport {
     fn get_url(&self) -> &str {
         &self.url
     }
-
-    fn finish_with_error(self, status: String) -> Self {
-        Self {
-            inputs: vec![EvalInput {
-                name: Some(StatusIcon::Warning.to_string()),
-                value: Some(status),
-                ..Default::default()
-            }],
-            ..self
-        }
-    }
 }
 
 impl BuildReport {
     #[must_use]
-    pub(super) fn from_url(url: &str) -> Self {
+    pub(crate) fn from_url(url: &str) -> Self {
         Self {
             url: url.to_string(),
-            inputs: vec![],
+            ..Default::default()
         }
     }
 
-    pub(super) fn fetch_and_read(self) -> anyhow::Result<Self> {
-        let doc = self.fetch_document()?;
-        let tbody = match self.find_tbody(&doc, "div#tabs-buildinputs") {
-            // inputs are essential information, so exit early if this fails:
-            Err(stat) => return Ok(stat),
-            Ok(tbody) => tbody,
+    /// Fetches the inputs of the build only, see [`Self::fetch_and_read_details()`]
+    /// for everything else.
+    pub(crate) fn fetch_and_read(self, client: &HydraClient) -> anyhow::Result<Self> {
+        let doc = match self.fetch_document(client.backend())? {
+            HydraDocument::Json(build) => {
+                // the build itself does not carry its inputs in JSON,
+                // so we read them off the first evaluation it belongs to
+                let Some(eval_id) = build["jobsetevals"].get(0).and_then(Value::as_u64) else {
+                    return Err(HydraError::NotEvaluated {
+                        message: format!("no evaluation found for the build at {}", self.url),
+                        url: self.url,
+                    }
+                    .into());
+                };
+                let url = format!("{}/eval/{eval_id}", client.host_url());
+                let inputs = match client.backend().fetch(&url)? {
+                    HydraDocument::Json(eval) => EvalInput::from_json(&eval),
+                    HydraDocument::Html(_) => bail!("no JSON form available for {url}"),
+                };
+                // the inputs of an eval are fixed once it is created
+                client.settle(&url);
+                return Ok(Self { inputs, ..self });
+            }
+            HydraDocument::Html(doc) => doc,
         };
-        let inputs = EvalInput::from_tbody(tbody, &self.url)?;
+        // inputs are essential information, so exit early if this fails:
+        let tbody = self.find_tbody(&doc, "div#tabs-buildinputs")?;
+        let inputs =
+            EvalInput::from_tbody(tbody, &self.url).map_err(|err| self.parse_error(&err))?;
         Ok(Self { inputs, ..self })
     }
+
+    /// Fetches all the details of the build, including its inputs.
+    pub(crate) fn fetch_and_read_details(self, client: &HydraClient) -> anyhow::Result<Self> {
+        let (stat, doc) = match self.fetch_document(client.backend())? {
+            HydraDocument::Json(build) => {
+                // the machine, the closure size and the inputs have no
+                // JSON form, so we fall back to the scraper for them
+                let doc = client.backend().fetch_html(&self.url)?;
+                (self.read_json(&build, client.host_url())?, doc)
+            }
+            HydraDocument::Html(doc) => {
+                // inputs are essential information, so exit early if this fails:
+                self.find_tbody(&doc, "div#tabs-buildinputs")?;
+                (self.read_html(&doc), doc)
+            }
+        };
+        let details = InfoTable::from_html(&doc);
+        let tbody = stat.find_tbody(&doc, "div#tabs-buildinputs")?;
+        let inputs =
+            EvalInput::from_tbody(tbody, &stat.url).map_err(|err| stat.parse_error(&err))?;
+        let stat = Self {
+            machine: details.text("machine"),
+            closure_size: details.text("closure size"),
+            inputs,
+            ..stat
+        };
+        if stat.stopped.is_some() {
+            // nothing changes on the page of a finished build anymore
+            client.settle(&stat.url);
+        }
+        Ok(stat)
+    }
+
+    /// Reads the details that are available from the JSON API of Hydra.
+    fn read_json(self, build: &Value, host_url: &str) -> anyhow::Result<Self> {
+        let time = |key: &str| build[key].as_u64().filter(|x| *x != 0);
+        let to_string = |value: &Value| value.as_str().map(str::to_string);
+        let objects = |key: &str| {
+            build[key]
+                .as_object()
+                .map(|x| x.values().cloned().collect::<Vec<_>>())
+                .unwrap_or_default()
+        };
+        let products = objects("buildproducts")
+            .iter()
+            .map(|product| BuildProduct {
+                name: to_string(&product["name"]),
+                product_type: to_string(&product["type"]),
+                path: to_string(&product["path"]),
+                size: product["filesize"].as_u64(),
+                sha256: to_string(&product["sha256hash"]),
+            })
+            .collect();
+        let (started, stopped) = (time("starttime"), time("stoptime"));
+        Ok(Self {
+            build: BuildStatus::from_json(build, host_url)?,
+            started: started.map(iso8601_from_unix),
+            stopped: stopped.map(iso8601_from_unix),
+            duration: stopped.zip(started).and_then(|(x, y)| x.checked_sub(y)),
+            drv_path: to_string(&build["drvpath"]),
+            outputs: objects("buildoutputs")
+                .iter()
+                .filter_map(|output| to_string(&output["path"]))
+                .collect(),
+            products,
+            ..self
+        })
+    }
+
+    /// Reads the details from the summary and the details tabs of a build page.
+    fn read_html(self, doc: &Html) -> Self {
+        let details = InfoTable::from_html(doc);
+        let status = details
+            .get("status")
+            .and_then(|x| x.find("img").ok())
+            .and_then(|x| x.attr("title"))
+            .map(str::to_string)
+            .or_else(|| details.text("status"))
+            .unwrap_or_default();
+        let [started, stopped] = ["started", "finished"].map(|label| {
+            let time = details.get(label)?.find("time").ok()?;
+            let timestamp: u64 = time.try_attr("data-timestamp").ok()?.parse().ok()?;
+            Some((timestamp, time.attr("datetime")?.to_string()))
+        });
+        let (success, icon) = match stopped {
+            Some(_) => BuildStatus::icon_from_status(&status),
+            None => (false, StatusIcon::Queued),
+        };
+        let build_id = self.url.rsplit('/').next().map(str::to_string);
+        let build = BuildStatus {
+            icon,
+            success,
+            status,
+            timestamp: stopped.as_ref().map(|x| x.1.clone()),
+            build_id,
+            build_url: Some(self.url.clone()),
+            name: details.text("nix name"),
+            arch: details.text("system"),
+            evals: true,
+            ..Default::default()
+        };
+        let duration = match (&started, &stopped) {
+            (Some((x, _)), Some((y, _))) => y.checked_sub(*x),
+            _ => None,
+        };
+        let products = doc
+            .find_all("table.info-table")
+            .into_iter()
+            .map(InfoTable::from_table)
+            .filter_map(|table| {
+                // file products come with a nested table of their details
+                let path = table.text("full path")?;
+                let size = table.text("file size").and_then(|x| {
+                    let bytes = Regex::new(r"([0-9]+) bytes").ok()?;
+                    bytes.captures(&x)?[1].parse().ok()
+                });
+                Some(BuildProduct {
+                    name: path.rsplit('/').next().map(str::to_string),
+                    product_type: Some("file".into()),
+                    size,
+                    sha256: table.text("sha-256 hash"),
+                    path: Some(path),
+                })
+            })
+            .collect();
+        Self {
+            build,
+            started: started.map(|x| x.1),
+            stopped: stopped.map(|x| x.1),
+            duration,
+            drv_path: details.text("derivation store path"),
+            outputs: details
+                .text("output store paths")
+                .unwrap_or_default()
+                .split([',', ' ', '\n'])
+                .filter(|x| x.starts_with('/'))
+                .map(str::to_string)
+                .collect(),
+            products,
+            ..self
+        }
+    }
+
+    /// Formats the details of the build as lines of `key: value`,
+    /// without the status, the products and the inputs.
+    fn format_details(&self) -> String {
+        let mut lines = Vec::new();
+        let mut push = |key: &str, value: Option<String>| {
+            if let Some(value) = value {
+                lines.push(format!("{}: {value}", key.bold()));
+            }
+        };
+        push("nix_name", self.build.name.clone());
+        push("system", self.build.arch.clone());
+        push("machine", self.machine.clone());
+        push("started", self.started.clone());
+        push("stopped", self.stopped.clone());
+        push("duration", self.duration.map(format_duration));
+        push("drv_path", self.drv_path.clone());
+        for output in &self.outputs {
+            push("output", Some(output.clone()));
+        }
+        push("closure_size", self.closure_size.clone());
+        lines.join("\n")
+    }
+
+    /// Follows the build steps, along with the failures that are propagated
+    /// from other builds, to the step that actually failed, e.g. the build of
+    /// a dependency for a build with `Dependency failed`.
+    pub(crate) fn find_failure_cause(
+        &self,
+        client: &HydraClient,
+    ) -> anyhow::Result<Option<FailureCause>> {
+        let mut build_url = self.url.clone();
+        for _ in 0..MAX_PROPAGATION_DEPTH {
+            let doc = client.backend().fetch_html(&build_url)?;
+            let failed_steps: Vec<_> = BuildStep::from_html(&doc)
+                .into_iter()
+                .filter(|step| step.failed)
+                .collect();
+            // prefer the steps with a log of their own, unlike e.g.
+            // a `Cached failure` that is propagated from another build
+            if let Some(step) = failed_steps.iter().find(|x| x.log_url.is_some()) {
+                return Ok(Some(step.clone().into_cause(&build_url)));
+            }
+            let Some(step) = failed_steps.first() else {
+                return Ok(None);
+            };
+            match &step.propagated_from {
+                Some(url) => build_url.clone_from(url),
+                None => return Ok(Some(step.clone().into_cause(&build_url))),
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Rows of the `table.info-table`s on a build page, which list the details
+/// as `<th>Label:</th><td>value</td>`, keyed by the lowercase labels.
+struct InfoTable<'a>(IndexMap<String, ElementRef<'a>>);
+
+impl<'a> InfoTable<'a> {
+    fn from_html(doc: &'a Html) -> Self {
+        let mut rows = IndexMap::new();
+        for table in doc.find_all("table.info-table") {
+            for (label, value) in Self::from_table(table).0 {
+                // the first one wins, e.g. over the tables of build products
+                rows.entry(label).or_insert(value);
+            }
+        }
+        Self(rows)
+    }
+
+    /// Reads the rows of a single table, excluding those of nested tables.
+    fn from_table(table: ElementRef<'a>) -> Self {
+        let mut rows = IndexMap::new();
+        for row in table.find_all("tr") {
+            let parent_table = row
+                .ancestors()
+                .filter_map(ElementRef::wrap)
+                .find(|x| x.value().name() == "table");
+            if parent_table.map(|x| x.id()) != Some(table.id()) {
+                continue;
+            }
+            let (Ok(label), Ok(value)) = (row.find("th"), row.find("td")) else {
+                continue;
+            };
+            let label: String = label.text().collect();
+            let label = label.trim().trim_end_matches(':').to_lowercase();
+            rows.entry(label).or_insert(value);
+        }
+        Self(rows)
+    }
+
+    /// Finds the value of the first label that contains `label`,
+    /// e.g. `finished` for `Build finished`.
+    fn get(&self, label: &str) -> Option<ElementRef<'a>> {
+        self.0
+            .iter()
+            .find_map(|(key, value)| key.contains(label).then_some(*value))
+    }
+
+    /// Finds the text of the first label that contains `label`,
+    /// with the whitespace normalized.
+    fn text(&self, label: &str) -> Option<String> {
+        let text: String = self.get(label)?.text().collect();
+        let text: Vec<_> = text.split_whitespace().collect();
+        Some(text.join(" ")).filter(|x| !x.is_empty())
+    }
+}
+
+/// Formats a duration in seconds similar to Hydra, e.g. `1h 2m 3s`.
+fn format_duration(seconds: u64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds % 3600 / 60, seconds % 60);
+    match (hours, minutes) {
+        (0, 0) => format!("{seconds}s"),
+        (0, _) => format!("{minutes}m {seconds}s"),
+        _ => format!("{hours}h {minutes}m {seconds}s"),
+    }
+}
+
+impl ResolvedArgs {
+    pub(crate) fn fetch_and_print_builds(&self, build_ids: &[u64]) -> anyhow::Result<bool> {
+        let url = |id: &u64| format!("{}/build/{id}", self.client.host_url());
+        if self.url {
+            for id in build_ids {
+                println!("{}", url(id));
+            }
+            return Ok(true);
+        }
+        let mut status = true;
+        let mut indexmap = IndexMap::new();
+        let mut renderer = Renderer::new(self.format);
+        let fetch = |id: &u64| BuildReport::from_url(&url(id)).fetch_and_read_details(&self.client);
+        fetch_in_order(build_ids, self.jobs, fetch, |idx, stat| {
+            let stat = stat?;
+            status &= stat.build.success;
+            if !self.format.is_table() {
+                renderer.push(&[("build", &build_ids[idx].to_string())], &stat)?;
+                indexmap.insert(build_ids[idx], stat);
+                return Ok(());
+            }
+            if idx > 0 && !self.short {
+                println!(); // vertical whitespace
+            }
+            println!(
+                "Build {} {}",
+                build_ids[idx].to_string().bold(),
+                format!("@ {}", stat.get_url()).dimmed(),
+            );
+            println!(
+                "{}",
+                stat.format_table(false, std::slice::from_ref(&stat.build))
+            );
+            if self.short {
+                return Ok(());
+            }
+            let details = stat.format_details();
+            if !details.is_empty() {
+                println!("\n{details}");
+            }
+            for entry in &stat.products {
+                println!(); // vertical separation
+                println!("{entry}");
+            }
+            for entry in &stat.inputs {
+                println!(); // vertical separation
+                println!("{entry}");
+            }
+            Ok(())
+        })?;
+        renderer.finish(&indexmap)?;
+        Ok(status)
+    }
+}
+
+/// A single step from the build steps of a build page, e.g. the build of
+/// a dependency.
+#[derive(Debug, Clone)]
+struct BuildStep {
+    /// Status text of the step, e.g. `Cached failure`
+    status: String,
+    /// Output paths of the step, e.g. `/nix/store/<hash>-hello-2.12.1`
+    outputs: Vec<String>,
+    /// Whether the step has failed, for whatever reason
+    failed: bool,
+    /// URL of the log of the step, if it exists
+    log_url: Option<String>,
+    /// URL of the build that the failure of the step is propagated from
+    propagated_from: Option<String>,
+}
+
+impl BuildStep {
+    /// Reads the steps from `div#tabs-buildsteps`, latest first.
+    fn from_html(doc: &Html) -> Vec<Self> {
+        let mut steps = Vec::new();
+        for row in doc.find_all("div#tabs-buildsteps tbody tr") {
+            // the last column contains the status, along with the links
+            let [_, what, .., status] = row.find_all("td")[..] else {
+                continue;
+            };
+            let outputs = what
+                .find("tt")
+                .map(|x| x.text().collect::<String>())
+                .unwrap_or_default();
+            let link = |prefix: &str| {
+                status
+                    .find_all("a")
+                    .into_iter()
+                    .find(|a| a.text().collect::<String>().trim().starts_with(prefix))
+                    .and_then(|a| a.attr("href"))
+                    .map(str::to_string)
+            };
+            // the status is followed by the links in parentheses
+            let text: String = status.text().collect();
+            let text = text.split('(').next().unwrap_or_default();
+            steps.push(Self {
+                status: text.split_whitespace().collect::<Vec<_>>().join(" "),
+                outputs: outputs.split_whitespace().map(str::to_string).collect(),
+                failed: status.find("span.error").is_ok(),
+                log_url: link("log"),
+                propagated_from: link("build "),
+            });
+        }
+        steps
+    }
+
+    /// Converts the step into the [`FailureCause`] of a build,
+    /// where `build_url` is the build that the step belongs to.
+    fn into_cause(self, build_url: &str) -> FailureCause {
+        // strip the store directory and the hash from the first output
+        let name = self.outputs.first().map(|path| {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            match name.split_once('-') {
+                Some((hash, name)) if hash.len() == 32 => name.to_string(),
+                _ => name.to_string(),
+            }
+        });
+        FailureCause {
+            status: self.status,
+            name,
+            build_id: build_url.rsplit('/').next().map(str::to_string),
+            build_url: Some(build_url.into()),
+            log_url: self.log_url,
+        }
+    }
+}
+
+/// Fetches the last `lines` of the log at `log_url`, e.g.
+/// <https://hydra.nixos.org/build/290062156/log>.
+pub(crate) fn fetch_log_tail(
+    client: &HydraClient,
+    log_url: &str,
+    lines: usize,
+) -> anyhow::Result<String> {
+    // the `tail` page only loads the log with a script, whereas the `raw`
+    // one redirects to the plain text of the whole log at `/log/<drv>`
+    let text = client.backend().fetch_text(&format!("{log_url}/raw"))?;
+    let text: Vec<_> = text.lines().collect();
+    Ok(text[text.len().saturating_sub(lines)..].join("\n"))
+}
+
+#[test]
+fn find_failed_build_steps() {
+    let doc = Html::parse_document(
+        r#"<div id="tabs-buildsteps"><table><tbody>
+        <tr><td>2</td><td>Build of <tt>/nix/store/6ckfrlydmxm8v6w1fnpv44bdgwfc9yhh-openssl-3.3.2</tt></td><td></td><td></td><td class="step-status">
+            <span class="error">Cached failure</span>
+            (propagated from <a href="https://hydra.nixos.org/build/1">build 1</a>)
+        </td></tr>
+        <tr><td>1</td><td>Build of ...</td><td></td><td></td><td class="step-status">
+            Succeeded
+            (<a class="row-link" href="https://hydra.nixos.org/build/2/nixlog/1">log</a>,
+            <a href="https://hydra.nixos.org/build/2/nixlog/1/raw">raw</a>,
+            <a href="https://hydra.nixos.org/build/2/nixlog/1/tail">tail</a>)
+        </td></tr>
+        </tbody></table></div>"#,
+    );
+    let steps = BuildStep::from_html(&doc);
+    assert_eq!(steps.len(), 2);
+    assert!(steps[0].failed && steps[0].log_url.is_none());
+    assert_eq!(steps[0].status, "Cached failure");
+    assert_eq!(
+        steps[0].propagated_from.as_deref(),
+        Some("https://hydra.nixos.org/build/1")
+    );
+    assert!(!steps[1].failed);
+    assert!(steps[1]
+        .log_url
+        .as_deref()
+        .is_some_and(|x| x.ends_with("/nixlog/1")));
+    let cause = steps[0]
+        .clone()
+        .into_cause("https://hydra.nixos.org/build/2");
+    assert_eq!(cause.name.as_deref(), Some("openssl-3.3.2"));
+    assert_eq!(cause.build_id.as_deref(), Some("2"));
+}
+
+#[test]
+fn read_build_details() {
+    let doc = Html::parse_document(
+        r#"<div id="tabs-summary"><table class="info-table">
+        <tr><th>Build ID:</th><td>42</td></tr>
+        <tr><th>Status:</th><td><img src="/static/images/checkmark_16.png" title="Succeeded"/> Success</td></tr>
+        <tr><th>System:</th><td><tt>x86_64-linux</tt></td></tr>
+        <tr><th>Nix name:</th><td><tt>hello-2.12.1</tt></td></tr>
+        <tr><th>Build products:</th><td><table class="info-table">
+            <tr><th>Full path:</th><td><tt>/nix/store/aaaa-hello-2.12.1/hello.tar.gz</tt></td></tr>
+            <tr><th>File size:</th><td>1024 bytes (0.00 MiB)</td></tr>
+            <tr><th>SHA-256 hash:</th><td><tt>abcd</tt></td></tr>
+        </table></td></tr>
+        </table></div>
+        <div id="tabs-details"><table class="info-table">
+        <tr><th>Build started:</th><td><time data-timestamp="1728561000" datetime="2024-10-10T11:50:00Z">2024-10-10</time></td></tr>
+        <tr><th>Build finished:</th><td><time data-timestamp="1728561599" datetime="2024-10-10T11:59:59Z">2024-10-10</time></td></tr>
+        <tr><th>Machine:</th><td><tt>builder-1</tt></td></tr>
+        <tr><th>Derivation store path:</th><td><tt>/nix/store/bbbb-hello-2.12.1.drv</tt></td></tr>
+        <tr><th>Output store paths:</th><td><tt>/nix/store/aaaa-hello-2.12.1</tt></td></tr>
+        <tr><th>Closure size:</th><td>34.19 MiB</td></tr>
+        </table></div>"#,
+    );
+    let stat = BuildReport::from_url("https://hydra.nixos.org/build/42").read_html(&doc);
+    assert!(stat.build.success);
+    assert_eq!(stat.build.build_id.as_deref(), Some("42"));
+    assert_eq!(stat.build.name.as_deref(), Some("hello-2.12.1"));
+    assert_eq!(stat.build.arch.as_deref(), Some("x86_64-linux"));
+    assert_eq!(stat.stopped.as_deref(), Some("2024-10-10T11:59:59Z"));
+    assert_eq!(stat.duration, Some(599));
+    assert_eq!(stat.outputs, ["/nix/store/aaaa-hello-2.12.1"]);
+    assert_eq!(stat.products.len(), 1);
+    assert_eq!(stat.products[0].size, Some(1024));
+    assert_eq!(stat.products[0].name.as_deref(), Some("hello.tar.gz"));
+    assert_eq!(
+        InfoTable::from_html(&doc).text("machine").as_deref(),
+        Some("builder-1")
+    );
+    assert_eq!(format_duration(599), "9m 59s");
+}
+
+#[test]
+fn replay_build() {
+    let client = crate::backends::replay_client();
+    let report = client.build(290_062_156).unwrap();
+    assert_snapshot!(serde_json::to_string_pretty(&report).unwrap(), @r#"
+        {
+          "url": "https://hydra.nixos.org/build/290062156",
+          "icon": "✔",
+          "success": true,
+          "status": "Succeeded",
+          "timestamp": "2025-02-22T07:47:19Z",
+          "build_id": "290062156",
+          "build_url": "https://hydra.nixos.org/build/290062156",
+          "name": "hello-2.12.1",
+          "arch": "x86_64-linux",
+          "evals": true,
+          "started": "2025-02-22T07:46:31Z",
+          "stopped": "2025-02-22T07:47:19Z",
+          "duration": 48,
+          "machine": "elated-minsky",
+          "drv_path": "/nix/store/1x4f9v3y6jdlgxzmm5l2bq5zd0n5d6fl-hello-2.12.1.drv",
+          "outputs": [
+            "/nix/store/a7hnr9dcmx3qkkn8a20g7md1wya5zc9l-hello-2.12.1"
+          ],
+          "closure_size": "32.89 MiB",
+          "products": [],
+          "inputs": [
+            {
+              "name": "nixpkgs",
+              "type": "Git checkout",
+              "value": "https://github.com/nixos/nixpkgs.git",
+              "revision": "1e9e641a3fc1b22fbdb823a99d8ff96692cc4fba",
+              "store_path": "/nix/store/ln479gq56q3kyzyl0mm00xglpmfpzqx4-source"
+            },
+            {
+              "name": "officialRelease",
+              "type": "Boolean",
+              "value": "false"
+            }
+          ]
+        }
+    "#);
+}
+
+#[test]
+fn replay_build_inputs() {
+    let client = crate::backends::replay_client();
+    let inputs = client.build_inputs(290_062_156).unwrap();
+    // the JSON form of a build has no inputs, so they are read off the JSON
+    // form of its evaluation instead
+    let client = client.with_backend(crate::BackendKind::Json);
+    let json_inputs = client.build_inputs(290_062_156).unwrap();
+    let revisions = |inputs: &[EvalInput]| -> Vec<_> {
+        inputs
+            .iter()
+            .map(|x| (x.name.clone(), x.value.clone(), x.revision.clone()))
+            .collect()
+    };
+    assert_eq!(revisions(&json_inputs), revisions(&inputs));
+}
+
+#[test]
+fn replay_failure_cause() {
+    let client = crate::backends::replay_client();
+    // the failure is propagated from the build of a dependency
+    let cause = client.failure_cause(289_870_125).unwrap().unwrap();
+    assert_snapshot!(serde_json::to_string_pretty(&cause).unwrap(), @r#"
+        {
+          "status": "Failed",
+          "name": "perl-5.40.0",
+          "build_id": "289870098",
+          "build_url": "https://hydra.nixos.org/build/289870098",
+          "log_url": "https://hydra.nixos.org/build/289870098/nixlog/1"
+        }
+    "#);
+    let log_url = cause.log_url.unwrap();
+    assert_snapshot!(fetch_log_tail(&client, &log_url, 3).unwrap(), @r#"
+        t/io/sem.t ................................................ FAILED at test 3
+        Failed 1/2703 test programs. 1/1214734 subtests failed.
+        make: *** [makefile:803: test_harness] Error 1
+    "#);
 }
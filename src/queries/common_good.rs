@@ -0,0 +1,282 @@
+//! A module that finds the newest evaluation of a jobset in which a whole
+//! set of packages succeeded, for `--common-good`.
+//!
+//! A successful build is usually shared by several evaluations of the
+//! jobset, so the evaluations of the successful builds of the packages are
+//! fetched one build of each package at a time, starting from the newest
+//! builds, until the packages share an evaluation.
+
+use std::collections::BTreeSet;
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use colored::Colorize;
+use log::{info, warn};
+use serde::Serialize;
+use serde_with::skip_serializing_none;
+
+use super::{
+    bisect::{fetch_build_evals, nixpkgs_input},
+    EvalReport, JobsetReport, PackageReport,
+};
+use crate::{
+    fetch_in_order, format_rows, render::Renderer, BuildStatus, EvalStatus, Evaluation,
+    FetchHydraReport, ResolvedArgs,
+};
+
+#[skip_serializing_none]
+#[derive(Serialize, Debug, Clone, Default)]
+/// The newest evaluation of a jobset in which all the packages succeeded
+pub(crate) struct CommonGoodReport {
+    jobset: String,
+    packages: Vec<String>,
+    /// ID of the newest evaluation in which all the packages succeeded
+    eval_id: Option<u64>,
+    eval_url: Option<String>,
+    /// Status of the evaluation, if it is among the recent evals of the jobset
+    eval: Option<EvalStatus>,
+    /// Full nixpkgs revision of the evaluation
+    rev: Option<String>,
+    /// URL of the source tarball of the revision, for inputs on GitHub
+    tarball_url: Option<String>,
+    /// Flake reference to the revision, for inputs on GitHub
+    flake_ref: Option<String>,
+    /// Packages without any success among the fetched builds
+    never_succeeded: Vec<String>,
+}
+
+/// Returns the `owner/repo` of a git repository on GitHub.
+fn github_repo(url: &str) -> Option<&str> {
+    let url = url.trim_end_matches('/');
+    let repo = url.strip_suffix(".git").unwrap_or(url);
+    repo.strip_prefix("https://github.com/")
+}
+
+/// Finds the newest evaluation shared by the successful `builds` of all the
+/// packages, latest first, with the `build_evals` fetched with at most `jobs`
+/// threads. The evaluations are fetched for the newest build of each package
+/// first, then for the next newest, and so on until the packages share an
+/// evaluation. As each evaluation has a single build of each job, the older
+/// builds cannot share a newer evaluation.
+fn find_common_good(
+    builds: &[Vec<BuildStatus>],
+    jobs: usize,
+    build_evals: impl Fn(&BuildStatus) -> Vec<u64> + Sync,
+) -> anyhow::Result<Option<u64>> {
+    if builds.is_empty() || builds.iter().any(Vec::is_empty) {
+        return Ok(None);
+    }
+    let mut evals = vec![BTreeSet::new(); builds.len()];
+    let rounds = builds.iter().map(Vec::len).max().unwrap_or_default();
+    for round in 0..rounds {
+        let pending: Vec<_> = builds
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, builds)| Some((idx, builds.get(round)?)))
+            .collect();
+        let fetch = |(_, build): &(usize, &BuildStatus)| build_evals(build);
+        fetch_in_order(&pending, jobs, fetch, |idx, ids| {
+            evals[pending[idx].0].extend(ids);
+            Ok(())
+        })?;
+        let (first, others) = evals.split_first().expect("there are some packages");
+        let common = first
+            .iter()
+            .rev()
+            .find(|id| others.iter().all(|x| x.contains(id)));
+        if let Some(id) = common {
+            return Ok(Some(*id));
+        }
+    }
+    Ok(None)
+}
+
+impl ResolvedArgs {
+    /// Fetches the successful builds of `package`, latest first.
+    fn fetch_good_builds(&self, package: &str) -> anyhow::Result<Vec<BuildStatus>> {
+        let stat = PackageReport::new(package, &self.jobset, &self.client, self.long)
+            .fetch_and_read(&self.client)?;
+        let builds = stat.builds.into_iter();
+        Ok(builds.filter(|x| x.evals && x.success).collect())
+    }
+
+    /// Fetches the evaluations of the jobset that contain the `build`.
+    fn fetch_good_evals(&self, build: &BuildStatus) -> Vec<u64> {
+        let Some(build_url) = &build.build_url else {
+            return vec![];
+        };
+        fetch_build_evals(&self.client, build_url, &self.jobset)
+            .inspect_err(|err| warn!("{err}"))
+            .unwrap_or_default()
+    }
+
+    /// Resolves the evaluation `id` into its nixpkgs revision.
+    fn read_common_good(&self, report: &mut CommonGoodReport, id: u64) -> anyhow::Result<()> {
+        let filter = report.packages.first().map(String::as_str);
+        let eval = EvalReport::new(Evaluation::new(id, filter, false), &self.client)
+            .fetch_and_read(&self.client)?;
+        report.eval_url = Some(format!("{}/eval/{id}", self.client.host_url()));
+        report.eval = JobsetReport::new(&self.jobset, &self.client)
+            .fetch_and_read(&self.client)?
+            .evals
+            .into_iter()
+            .find(|x| x.id == Some(id));
+        let Some(input) = nixpkgs_input(&eval.inputs) else {
+            warn!("could not find the nixpkgs revision of {}", eval.get_url());
+            return Ok(());
+        };
+        report.rev.clone_from(&input.revision);
+        let repo = input.value.as_deref().and_then(github_repo);
+        if let (Some(repo), Some(rev)) = (repo, &report.rev) {
+            report.tarball_url = Some(format!("https://github.com/{repo}/archive/{rev}.tar.gz"));
+            report.flake_ref = Some(format!("github:{repo}/{rev}"));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn fetch_and_print_common_good<'a>(
+        &'a self,
+        packages: &'a [String],
+    ) -> anyhow::Result<bool> {
+        if self.url {
+            for package in packages {
+                let stat = PackageReport::new(package, &self.jobset, &self.client, self.long);
+                println!("{}", stat.get_url());
+            }
+            return Ok(true);
+        }
+        if self.format.is_table() {
+            println!(
+                "Newest evaluation of jobset {} where all of {} packages succeeded",
+                self.jobset.bold(),
+                packages.len().to_string().bold(),
+            );
+        }
+        let mut report = CommonGoodReport {
+            jobset: self.jobset.clone(),
+            packages: packages.to_vec(),
+            ..Default::default()
+        };
+        let mut builds = Vec::new();
+        let fetch = |package: &'a String| self.fetch_good_builds(package);
+        fetch_in_order(packages, self.jobs, fetch, |idx, good_builds| {
+            let good_builds = good_builds?;
+            if good_builds.is_empty() {
+                report.never_succeeded.push(packages[idx].clone());
+            }
+            builds.push(good_builds);
+            Ok(())
+        })?;
+        let build_evals = |build: &BuildStatus| self.fetch_good_evals(build);
+        report.eval_id = find_common_good(&builds, self.jobs, build_evals)?;
+        if let Some(id) = report.eval_id {
+            self.read_common_good(&mut report, id)?;
+        }
+        let found = report.rev.is_some();
+        if !self.format.is_table() {
+            let mut renderer = Renderer::new(self.format);
+            renderer.push(&[("jobset", &self.jobset)], &report)?;
+            renderer.finish(&report)?;
+            return Ok(found);
+        }
+        let Some(eval_url) = &report.eval_url else {
+            if report.never_succeeded.is_empty() {
+                warn!("the packages have no successful evaluation in common, try --long");
+            } else {
+                warn!(
+                    "no success found for: {}, try --long",
+                    report.never_succeeded.join(", ")
+                );
+            }
+            return Ok(false);
+        };
+        if let Some(eval) = &report.eval {
+            println!("{}", format_rows(false, std::slice::from_ref(eval)));
+        }
+        println!();
+        println!("{}: {eval_url}", "eval".bold());
+        if let Some(rev) = &report.rev {
+            println!("{}: {rev}", "revision".bold());
+        }
+        if let Some(url) = &report.tarball_url {
+            println!("{}: {url}", "tarball".bold());
+        }
+        if let Some(flake_ref) = &report.flake_ref {
+            info!("to pin nixpkgs in a flake to this revision, run:");
+            println!("nix flake lock --override-input nixpkgs {flake_ref}");
+        }
+        Ok(found)
+    }
+}
+
+#[test]
+fn github_repo_from_input() {
+    for url in [
+        "https://github.com/NixOS/nixpkgs.git",
+        "https://github.com/NixOS/nixpkgs",
+        "https://github.com/NixOS/nixpkgs/",
+    ] {
+        assert_eq!(github_repo(url), Some("NixOS/nixpkgs"));
+    }
+    assert_eq!(github_repo("https://git.example.com/nixpkgs.git"), None);
+}
+
+#[test]
+fn find_common_good_early() {
+    let client = crate::backends::replay_client();
+    let good_builds = |package| {
+        let stat = PackageReport::new(package, "nixpkgs/unstable", &client, false)
+            .fetch_and_read(&client)
+            .unwrap();
+        let builds = stat.builds.into_iter();
+        builds.filter(|x| x.success).collect::<Vec<_>>()
+    };
+    let builds = [
+        good_builds("hello.x86_64-linux"),
+        good_builds("hello.aarch64-linux"),
+    ];
+    let find = |evals: &[(&str, &[u64])]| {
+        let fetched = AtomicUsize::new(0);
+        let build_evals = |build: &BuildStatus| {
+            fetched.fetch_add(1, Ordering::Relaxed);
+            let id = build.build_id.as_deref().unwrap();
+            let (_, ids) = evals.iter().find(|(x, _)| *x == id).unwrap();
+            ids.to_vec()
+        };
+        let common = find_common_good(&builds, 2, build_evals).unwrap();
+        (common, fetched.into_inner())
+    };
+
+    // the newest builds share evaluations, so the older ones are skipped
+    let evals: &[(&str, &[u64])] = &[
+        ("290062156", &[1_809_801, 1_809_808]),
+        ("290062157", &[1_809_801, 1_809_808, 1_809_812]),
+        ("289504237", &[1_809_700]),
+        ("289504240", &[1_809_700]),
+    ];
+    assert_eq!(find(evals), (Some(1_809_808), 2));
+
+    // the newest builds are from different evaluations
+    let evals: &[(&str, &[u64])] = &[
+        ("290062156", &[1_809_808]),
+        ("290062157", &[1_809_812]),
+        ("289504237", &[1_809_690, 1_809_700]),
+        ("289504240", &[1_809_700, 1_809_705]),
+    ];
+    assert_eq!(find(evals), (Some(1_809_700), 4));
+
+    let evals: &[(&str, &[u64])] = &[
+        ("290062156", &[1_809_808]),
+        ("290062157", &[1_809_812]),
+        ("289504237", &[1_809_690]),
+        ("289504240", &[1_809_705]),
+    ];
+    assert_eq!(find(evals), (None, 4));
+
+    // a package that never succeeded
+    let none = [builds[0].clone(), vec![]];
+    assert_eq!(
+        find_common_good(&none, 2, |_| unreachable!()).unwrap(),
+        None
+    );
+}
@@ -0,0 +1,388 @@
+//! A module that tracks whether a nixpkgs commit has reached the channels,
+//! for the `contains` subcommand.
+//!
+//! Channels only ever advance, so the evaluations of a jobset that contain
+//! the commit are always the latest ones, and the first of them can be
+//! found by a binary search over the recent evaluations, comparing the
+//! commit against their nixpkgs revisions.
+
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use anyhow::bail;
+use colored::Colorize;
+use log::{info, warn};
+use serde::Serialize;
+use serde_with::skip_serializing_none;
+
+use super::{bisect::nixpkgs_input, EvalReport, JobsetReport, PackageReport};
+use crate::{
+    args::aggregate_job, backends::Http, error::catch_missing, format_rows, render::Renderer,
+    BuildStatus, EvalStatus, Evaluation, FetchHydraReport, HydraClient, ReleaseStatus,
+    ResolvedArgs, StatusIcon,
+};
+
+/// Default channels to check for the `contains` subcommand,
+/// from the fastest to the slowest to advance.
+pub(crate) const DEFAULT_CHANNELS: [&str; 3] =
+    ["nixos-unstable-small", "nixpkgs-unstable", "nixos-unstable"];
+
+/// How to tell whether a commit is part of the history of a revision
+#[derive(Debug, Clone, Copy)]
+enum Ancestry<'a> {
+    /// With `git merge-base` in a local checkout of nixpkgs
+    Git(&'a Path),
+    /// With the compare API of GitHub, for the given `owner/repo`
+    GitHub(&'a str),
+}
+
+impl Ancestry<'_> {
+    /// Checks whether `commit` is an ancestor of (or the same as) `rev`,
+    /// where both may be abbreviated.
+    fn is_ancestor(self, http: &Http, commit: &str, rev: &str) -> anyhow::Result<bool> {
+        if rev.starts_with(commit) || commit.starts_with(rev) {
+            return Ok(true);
+        }
+        match self {
+            Self::Git(dir) => {
+                let status = Command::new("git")
+                    .arg("-C")
+                    .arg(dir)
+                    .args(["merge-base", "--is-ancestor", commit, rev])
+                    .stderr(Stdio::null())
+                    .status()?;
+                match status.code() {
+                    Some(0) => Ok(true),
+                    Some(1) => Ok(false),
+                    _ => bail!(
+                        "could not compare {commit} with {rev} in {}, is the checkout up to date?",
+                        dir.display()
+                    ),
+                }
+            }
+            Self::GitHub(repo) => {
+                let url = format!(
+                    "https://api.github.com/repos/{repo}/compare/{commit}...{rev}?per_page=1"
+                );
+                let json = http.get_json(&url)?;
+                // the comparison of two commits never changes
+                http.settle(&url);
+                match json["status"].as_str() {
+                    Some("ahead" | "identical") => Ok(true),
+                    Some("behind" | "diverged") => Ok(false),
+                    _ => bail!("could not compare {commit} with {rev}: {}", json["message"]),
+                }
+            }
+        }
+    }
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Debug, Clone, Default)]
+/// Whether a nixpkgs commit has reached a channel (or jobset)
+pub(crate) struct ContainsReport {
+    channel: Option<String>,
+    jobset: String,
+    /// The first evaluation of the jobset that contains the commit
+    eval: Option<EvalStatus>,
+    /// Full nixpkgs revision of the evaluation
+    rev: Option<String>,
+    /// Nixpkgs revisions before and at the evaluation,
+    /// i.e. the range of commits that the evaluation brought in
+    revs: Option<(String, String)>,
+    /// Build of the aggregate job for the release tests of the evaluation
+    test: Option<BuildStatus>,
+    /// Current nixpkgs revision of the channel
+    channel_rev: Option<String>,
+    /// Whether the channel has advanced to (or past) the evaluation
+    released: Option<bool>,
+}
+
+impl ContainsReport {
+    /// Finds the first evaluation of `jobset` that contains `commit`, where
+    /// `is_ancestor(commit, rev)` tells whether `commit` is part of `rev`,
+    /// see [`Ancestry::is_ancestor()`].
+    fn fetch(
+        client: &HydraClient,
+        commit: &str,
+        channel: Option<&str>,
+        jobset: &str,
+        is_ancestor: impl Fn(&str, &str) -> anyhow::Result<bool>,
+    ) -> anyhow::Result<Self> {
+        let mut stat = Self {
+            channel: channel.map(str::to_string),
+            jobset: jobset.into(),
+            ..Default::default()
+        };
+        let contains = |rev: &str| is_ancestor(commit, rev);
+        // recent evaluations, latest first
+        let evals = JobsetReport::new(jobset, client)
+            .fetch_and_read(client)?
+            .evals;
+        let evals: Vec<_> = evals
+            .into_iter()
+            .filter(|x| x.short_rev.is_some())
+            .collect();
+        // invariant: evals[..lo] contain the commit, while evals[hi..] do not
+        let (mut lo, mut hi) = (0, evals.len());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            let short_rev = evals[mid].short_rev.as_deref().unwrap_or_default();
+            if contains(short_rev)? {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        let Some(eval) = lo.checked_sub(1).map(|idx| evals[idx].clone()) else {
+            return Ok(stat); // not yet evaluated
+        };
+        if lo == evals.len() {
+            warn!(
+                "all the recent evaluations of {jobset} contain {commit}, {}",
+                "so the first of them might be even older"
+            );
+        }
+        let Some(id) = eval.id else {
+            return Ok(Self {
+                eval: Some(eval),
+                ..stat
+            });
+        };
+
+        // the full revision and the range of commits of the evaluation
+        let job = aggregate_job(jobset);
+        let report = EvalReport::new(Evaluation::new(id, Some(job), false), client)
+            .fetch_and_read(client)?;
+        if let Some(input) = nixpkgs_input(&report.inputs) {
+            stat.rev.clone_from(&input.revision);
+            stat.revs = report
+                .changes
+                .iter()
+                .find(|x| input.name.as_ref() == Some(&x.input))
+                .and_then(|x| x.revs.clone());
+        }
+
+        // the release test of the evaluation, with the existing release logic
+        let tests = PackageReport::new(job, jobset, client, true).fetch_and_read(client);
+        let tests = catch_missing(tests.map(|x| x.builds))?.unwrap_or_default();
+        let release = ReleaseStatus::join(
+            vec![eval.clone()],
+            &tests,
+            channel.unwrap_or_default(),
+            jobset,
+            client,
+        )
+        .pop();
+        stat.test = release.map(|x| x.test);
+        stat.eval = Some(eval);
+
+        // see: https://channels.nixos.org
+        let is_channel = |x: &&str| x.starts_with("nixos-") || x.starts_with("nixpkgs-");
+        if let Some(channel) = channel.filter(is_channel) {
+            if client.is_default_host() {
+                let url = format!("https://channels.nixos.org/{channel}/git-revision");
+                match client.http().get_text(&url) {
+                    Ok(channel_rev) => {
+                        let channel_rev = channel_rev.trim().to_string();
+                        let rev = stat.rev.as_deref().unwrap_or(commit);
+                        stat.released = Some(is_ancestor(rev, &channel_rev)?);
+                        stat.channel_rev = Some(channel_rev);
+                    }
+                    Err(err) => warn!("could not fetch the revision of {channel}: {err}"),
+                }
+            }
+        }
+        Ok(stat)
+    }
+}
+
+impl ResolvedArgs {
+    pub(crate) fn fetch_and_print_contains(
+        &self,
+        commit: &str,
+        targets: &[(Option<String>, String)],
+        git_dir: Option<&Path>,
+    ) -> anyhow::Result<bool> {
+        if self.url {
+            for (_, jobset) in targets {
+                println!("{}", JobsetReport::new(jobset, &self.client).get_url());
+            }
+            return Ok(true);
+        }
+        let ancestry = match git_dir {
+            Some(dir) => Ancestry::Git(dir),
+            None => {
+                info!("comparing commits on GitHub, or use --git-dir with a nixpkgs checkout");
+                Ancestry::GitHub("NixOS/nixpkgs")
+            }
+        };
+        let mut status = true;
+        let mut reports = Vec::new();
+        let mut renderer = Renderer::new(self.format);
+        for (idx, (channel, jobset)) in targets.iter().enumerate() {
+            if self.format.is_table() {
+                if idx > 0 {
+                    println!(); // vertical whitespace
+                }
+                let url = JobsetReport::new(jobset, &self.client)
+                    .get_url()
+                    .to_string();
+                println!(
+                    "First evaluation of jobset {} with {} {}",
+                    jobset.bold(),
+                    commit.bold(),
+                    format!("@ {url}").dimmed(),
+                );
+            }
+            let is_ancestor =
+                |commit: &str, rev: &str| ancestry.is_ancestor(self.client.http(), commit, rev);
+            let stat = ContainsReport::fetch(
+                &self.client,
+                commit,
+                channel.as_deref(),
+                jobset,
+                is_ancestor,
+            )?;
+            status &= stat.eval.is_some();
+            if !self.format.is_table() {
+                renderer.push(&[("commit", commit)], &stat)?;
+                reports.push(stat);
+                continue;
+            }
+            let Some(eval) = &stat.eval else {
+                println!("{} not evaluated yet", StatusIcon::Queued);
+                continue;
+            };
+            println!("{}", format_rows(false, std::slice::from_ref(eval)));
+            let mut lines = Vec::new();
+            if let Some(rev) = &stat.rev {
+                lines.push(("revision", rev.clone()));
+            }
+            if let Some((from, to)) = &stat.revs {
+                lines.push(("revs", format!("{from} -> {to}")));
+            }
+            let test = match &stat.test {
+                Some(test) => format!(
+                    "{} {} {}",
+                    test.icon,
+                    test.status,
+                    test.build_url.as_deref().unwrap_or_default().dimmed()
+                ),
+                None => format!("{} no build of the release tests", StatusIcon::Queued),
+            };
+            lines.push(("tested", test));
+            if let (Some(channel), Some(released)) = (&stat.channel, stat.released) {
+                let channel_rev = stat.channel_rev.as_deref().unwrap_or_default();
+                let icon = match released {
+                    true => StatusIcon::Succeeded,
+                    false => StatusIcon::Queued,
+                };
+                lines.push(("channel", format!("{icon} {channel} @ {channel_rev}")));
+            }
+            for (key, value) in lines {
+                println!("{}: {value}", key.bold());
+            }
+        }
+        renderer.finish(&reports)?;
+        Ok(status)
+    }
+}
+
+#[test]
+fn replay_contains() {
+    let client = crate::backends::replay_client();
+    // a linear history of nixpkgs, oldest first, along the fixtures
+    let history = [
+        "1e9e641a3fc1b22fbdb823a99d8ff96692cc4fba",
+        "5d8f4beac036a6f9d9b1aaf0bbc4c2ad1a0e0d8b",
+        "8c4dc69b9732f6bbe826b5fbb32184987520ff26",
+        "0196c0175e9191c474c26ab5548db27ef5d34b05",
+        "2d6cf8ef7f97a5b1e3a4f2d0c9b8e7f6a5d4c3b2",
+    ];
+    let comparisons = std::cell::Cell::new(0);
+    let is_ancestor = |commit: &str, rev: &str| {
+        comparisons.set(comparisons.get() + 1);
+        let idx = |rev: &str| history.iter().position(|x| x.starts_with(rev)).unwrap();
+        Ok(idx(commit) <= idx(rev))
+    };
+    let fetch = |commit, channel| {
+        comparisons.set(0);
+        ContainsReport::fetch(&client, commit, channel, "nixpkgs/unstable", is_ancestor).unwrap()
+    };
+
+    // in the two latest evals, and the channel has advanced past them
+    let stat = fetch(history[1], Some("nixpkgs-unstable"));
+    assert_eq!(stat.eval.and_then(|x| x.id), Some(1_812_301));
+    assert_eq!(stat.rev.as_deref(), Some(history[2]));
+    assert_eq!(stat.revs, Some((history[0].into(), history[2].into())));
+    let test = stat.test.unwrap();
+    assert_eq!(test.build_id.as_deref(), Some("290087455"));
+    assert_eq!(stat.channel_rev.as_deref(), Some(history[3]));
+    assert_eq!(stat.released, Some(true));
+    // a binary search over the 3 evals, then the channel
+    assert_eq!(comparisons.get(), 3);
+
+    // the channel is still at the eval before
+    let stat = fetch(history[1], Some("nixos-unstable"));
+    assert_eq!(stat.eval.and_then(|x| x.id), Some(1_812_301));
+    assert_eq!(stat.channel_rev.as_deref(), Some(history[0]));
+    assert_eq!(stat.released, Some(false));
+
+    // not evaluated yet
+    let stat = fetch(history[4], Some("nixpkgs-unstable"));
+    assert!(stat.eval.is_none() && stat.rev.is_none() && stat.released.is_none());
+}
+
+#[test]
+fn github_ancestry() {
+    let client = crate::backends::replay_client();
+    let ancestry = Ancestry::GitHub("NixOS/nixpkgs");
+    let is_ancestor = |commit, rev| ancestry.is_ancestor(client.http(), commit, rev).unwrap();
+    assert!(is_ancestor("8c4dc69b9732", "0196c01"));
+    assert!(!is_ancestor("0196c01", "8c4dc69b9732"));
+    // the same commit, without asking GitHub
+    assert!(is_ancestor(
+        "0196c01",
+        "0196c0175e9191c474c26ab5548db27ef5d34b05"
+    ));
+}
+
+#[test]
+fn git_ancestry() {
+    let dir = std::env::temp_dir().join(format!("hydra-check-git-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&dir)
+            .args([
+                "-c",
+                "user.name=hydra-check",
+                "-c",
+                "user.email=hydra-check@localhost",
+            ])
+            .args(["-c", "commit.gpgsign=false"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?}");
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    };
+    git(&["init", "--quiet"]);
+    let mut commits = Vec::new();
+    for message in ["first", "second"] {
+        git(&["commit", "--quiet", "--allow-empty", "--message", message]);
+        commits.push(git(&["rev-parse", "HEAD"]));
+    }
+    let client = crate::backends::replay_client();
+    let ancestry = Ancestry::Git(&dir);
+    let is_ancestor = |commit, rev| ancestry.is_ancestor(client.http(), commit, rev);
+    assert!(is_ancestor(&commits[0], &commits[1]).unwrap());
+    assert!(!is_ancestor(&commits[1], &commits[0]).unwrap());
+    // e.g. a checkout that is not up to date
+    assert!(is_ancestor("0196c0175e9191c474c26ab5548db27ef5d34b05", &commits[1]).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -19,19 +19,27 @@ use std::fmt::Display;
 #[cfg(test)]
 use insta::assert_snapshot;
 
+use super::watch::Poll;
 use crate::{
-    constants::HYDRA_CHECK_HOST_URL, BuildStatus, EvalInput, Evaluation, FetchHydraReport,
-    ResolvedArgs, SoupFind, StatusIcon,
+    fetch_in_order, render::Renderer, BuildStatus, EvalInput, Evaluation, FetchHydraReport,
+    HydraClient, ResolvedArgs, SoupFind,
 };
 
+#[non_exhaustive]
 #[skip_serializing_none]
-#[derive(Serialize, Clone)]
-struct EvalInputChanges {
-    input: String,
-    description: String,
-    url: Option<String>,
-    revs: Option<(String, String)>,
-    short_revs: Option<(String, String)>,
+#[derive(Serialize, Clone, Debug)]
+/// Changes of a single input of an evaluation, compared to the previous one
+pub struct EvalInputChanges {
+    /// Name of the changed input, e.g. `nixpkgs`
+    pub input: String,
+    /// Description of the changes, e.g. `8c4dc69b9732 to 1e9e641a3fc1`
+    pub description: String,
+    /// URL of the changes on Hydra
+    pub url: Option<String>,
+    /// Full git revisions before and after the changes
+    pub revs: Option<(String, String)>,
+    /// Short git revisions before and after the changes
+    pub short_revs: Option<(String, String)>,
 }
 
 impl Display for EvalInputChanges {
@@ -176,50 +184,61 @@ impl EvalInputChanges {
     }
 }
 
-#[derive(Serialize, Clone)]
-struct EvalReport<'a> {
+#[non_exhaustive]
+#[derive(Serialize, Clone, Debug)]
+/// Details of a single evaluation, including its comparison with the
+/// previous evaluation of the jobset
+pub struct EvalReport {
+    /// Specification of the evaluation
     #[serde(flatten)]
-    eval: &'a Evaluation,
-    url: String,
-    inputs: Vec<EvalInput>,
-    changes: Vec<EvalInputChanges>,
-    aborted: Vec<BuildStatus>,
-    now_fail: Vec<BuildStatus>,
-    now_succeed: Vec<BuildStatus>,
-    new: Vec<BuildStatus>,
-    removed: Vec<BuildStatus>,
-    still_fail: Vec<BuildStatus>,
-    still_succeed: Vec<BuildStatus>,
-    unfinished: Vec<BuildStatus>,
+    pub eval: Evaluation,
+    /// URL of the evaluation on Hydra
+    pub url: String,
+    /// Inputs of the evaluation
+    pub inputs: Vec<EvalInput>,
+    /// Changed inputs, compared to the previous evaluation
+    pub changes: Vec<EvalInputChanges>,
+    /// Builds that were aborted or timed out
+    pub aborted: Vec<BuildStatus>,
+    /// Builds that are newly failing
+    pub now_fail: Vec<BuildStatus>,
+    /// Builds that are newly succeeding
+    pub now_succeed: Vec<BuildStatus>,
+    /// Builds of new jobs
+    pub new: Vec<BuildStatus>,
+    /// Removed jobs
+    pub removed: Vec<BuildStatus>,
+    /// Builds that are still failing
+    pub still_fail: Vec<BuildStatus>,
+    /// Builds that are still succeeding
+    pub still_succeed: Vec<BuildStatus>,
+    /// Builds that are still queued
+    pub unfinished: Vec<BuildStatus>,
 }
 
-impl FetchHydraReport for EvalReport<'_> {
+impl FetchHydraReport for EvalReport {
     fn get_url(&self) -> &str {
         &self.url
     }
-
-    fn finish_with_error(self, status: String) -> Self {
-        Self {
-            inputs: vec![EvalInput {
-                name: Some(StatusIcon::Warning.to_string()),
-                value: Some(status),
-                ..Default::default()
-            }],
-            ..self
-        }
-    }
 }
 
-impl<'a> From<&'a Evaluation> for EvalReport<'a> {
-    fn from(eval: &'a Evaluation) -> Self {
-        let mut url = format!("{}/eval/{}", &*HYDRA_CHECK_HOST_URL, eval.id);
-        let mut filtered = false;
+impl EvalReport {
+    pub(crate) fn new(eval: Evaluation, client: &HydraClient) -> Self {
+        let mut url = format!("{}/eval/{}", client.host_url(), eval.id);
+        let mut params = Vec::new();
         if let Some(filter) = &eval.filter {
-            url = format!("{url}?filter={filter}");
-            filtered = true;
+            params.push(format!("filter={filter}"));
         }
         if eval.long {
-            url = format!("{url}{}full=1", if filtered { "&" } else { "?" });
+            params.push("full=1".into());
+        }
+        if let Some(base) = eval.compare {
+            // Hydra compares against any given eval, see `view_GET` in:
+            // https://github.com/NixOS/hydra/blob/master/src/lib/Hydra/Controller/JobsetEval.pm
+            params.push(format!("compare={base}"));
+        }
+        if !params.is_empty() {
+            url = format!("{url}?{}", params.join("&"));
         }
         Self {
             eval,
@@ -236,32 +255,55 @@ impl<'a> From<&'a Evaluation> for EvalReport<'a> {
             unfinished: vec![],
         }
     }
-}
 
-impl EvalReport<'_> {
+    /// Builds of the evaluation by the kind of change, named after the
+    /// fields, e.g. `now_fail`.
+    fn builds_by_change(&self) -> [(&'static str, &[BuildStatus]); 8] {
+        [
+            ("aborted", &self.aborted),
+            ("now_fail", &self.now_fail),
+            ("now_succeed", &self.now_succeed),
+            ("new", &self.new),
+            ("removed", &self.removed),
+            ("still_fail", &self.still_fail),
+            ("still_succeed", &self.still_succeed),
+            ("unfinished", &self.unfinished),
+        ]
+    }
+
+    /// Title of the evaluation for printing, e.g.
+    /// `Evaluation 1809808 filtered by 'hello' @ https://hydra.nixos.org/eval/1809808?filter=hello`.
+    fn heading(&self) -> String {
+        format!(
+            "Evaluation {}{}{} {}",
+            self.eval.id.to_string().bold(),
+            match &self.eval.filter {
+                Some(x) => format!(" filtered by '{}'", x.bold()),
+                None => "".into(),
+            },
+            match &self.eval.compare {
+                Some(x) => format!(" compared to {}", x.to_string().bold()),
+                None => "".into(),
+            },
+            format!("@ {}", self.get_url()).dimmed(),
+        )
+    }
+
     fn parse_build_stats(&self, doc: &Html, selector: &str) -> anyhow::Result<Vec<BuildStatus>> {
-        let err = || {
-            anyhow!(
-                "could not parse the table of build stats '{:?}' in {}",
-                selector,
-                doc.html()
-            )
-        };
-        let tbody = match self.find_tbody(doc, selector) {
-            Err(stat) => bail!("{:?}", stat.inputs.first().ok_or_else(err)?.value),
-            Ok(tbody) => tbody,
-        };
-        BuildStatus::from_tbody(tbody)
+        let tbody = self.find_tbody(doc, selector)?;
+        Ok(BuildStatus::from_tbody(tbody).map_err(|err| self.parse_error(&err))?)
     }
 
-    fn fetch_and_read(self) -> anyhow::Result<Self> {
-        let doc = self.fetch_document()?;
-        let tbody = match self.find_tbody(&doc, "div#tabs-inputs") {
-            // inputs are essential information, so exit early if this fails:
-            Err(stat) => return Ok(stat),
-            Ok(tbody) => tbody,
-        };
-        let inputs = EvalInput::from_tbody(tbody, self.eval.id.to_string().as_str())?;
+    /// Reads the evaluation off its web page with any backend, since the
+    /// input changes and the comparison with the previous evaluation have no
+    /// JSON form. The JSON form of an evaluation is only read for the inputs
+    /// of a build, see [`super::BuildReport::fetch_and_read()`].
+    pub(crate) fn fetch_and_read(self, client: &HydraClient) -> anyhow::Result<Self> {
+        let doc = client.backend().fetch_html(&self.url)?;
+        // inputs are essential information, so exit early if this fails:
+        let tbody = self.find_tbody(&doc, "div#tabs-inputs")?;
+        let inputs = EvalInput::from_tbody(tbody, self.eval.id.to_string().as_str())
+            .map_err(|err| self.parse_error(&err))?;
         let changes = EvalInputChanges::from_html(&doc).unwrap_or_else(|err| {
             warn!("{}\n{}", err, err.backtrace());
             vec![]
@@ -286,6 +328,10 @@ impl EvalReport<'_> {
                         vec![]
                     })
             });
+        if !inputs.is_empty() && unfinished.is_empty() {
+            // nothing changes on the page of a finished eval anymore
+            client.settle(&self.url);
+        }
 
         Ok(Self {
             inputs,
@@ -304,9 +350,10 @@ impl EvalReport<'_> {
 }
 
 impl ResolvedArgs {
-    pub(crate) fn fetch_and_print_evaluations(&self, evals: &[Evaluation]) -> anyhow::Result<bool> {
-        let mut indexmap = IndexMap::new();
-        let evals: Vec<_> = match evals.iter().any(|eval| eval.id == 0) {
+    /// Resolves the latest evaluation of the jobset (with `id == 0`),
+    /// printing the recent evaluations of the jobset along the way.
+    fn resolve_evaluations(&self, evals: &[Evaluation]) -> anyhow::Result<Vec<Evaluation>> {
+        Ok(match evals.iter().any(|eval| eval.id == 0) {
             false => evals.to_owned(),
             true => {
                 info!(
@@ -330,33 +377,37 @@ impl ResolvedArgs {
                     })
                     .collect()
             }
-        };
-        for (idx, eval) in evals.iter().enumerate() {
-            let stat = EvalReport::from(eval);
-            if self.url {
-                println!("{}", stat.get_url());
-                continue;
+        })
+    }
+
+    pub(crate) fn fetch_and_print_evaluations(&self, evals: &[Evaluation]) -> anyhow::Result<bool> {
+        let mut indexmap = IndexMap::new();
+        let evals = self.resolve_evaluations(evals)?;
+        if self.url {
+            for eval in &evals {
+                println!("{}", EvalReport::new(eval.clone(), &self.client).get_url());
             }
-            if !self.json {
-                // print title first, then fetch
-                if idx > 0 && !self.short {
-                    println!(); // vertical whitespace
+            return Ok(true);
+        }
+        let mut renderer = Renderer::new(self.format);
+        let fetch = |eval: &Evaluation| {
+            EvalReport::new(eval.clone(), &self.client).fetch_and_read(&self.client)
+        };
+        fetch_in_order(&evals, self.jobs, fetch, |idx, stat| {
+            let stat = stat?;
+            if !self.format.is_table() {
+                for (change, builds) in stat.builds_by_change() {
+                    for build in builds {
+                        renderer.push(&[("eval", &stat.eval.spec), ("change", change)], build)?;
+                    }
                 }
-                println!(
-                    "Evaluation {}{} {}",
-                    stat.eval.id.to_string().bold(),
-                    match &stat.eval.filter {
-                        Some(x) => format!(" filtered by '{}'", x.bold()),
-                        None => "".into(),
-                    },
-                    format!("@ {}", stat.get_url()).dimmed(),
-                );
+                indexmap.insert(stat.eval.spec.clone(), stat);
+                return Ok(());
             }
-            let stat = stat.fetch_and_read()?;
-            if self.json {
-                indexmap.insert(&stat.eval.spec, stat);
-                continue;
+            if idx > 0 && !self.short {
+                println!(); // vertical whitespace
             }
+            println!("{}", stat.heading());
             for entry in &stat.inputs {
                 println!(); // vertical separation
                 println!("{entry}");
@@ -366,7 +417,7 @@ impl ResolvedArgs {
                 println!("{entry}");
             }
             if self.short {
-                continue;
+                return Ok(());
             }
             for (build_stats, prompt) in [
                 (&stat.aborted, "Aborted / Timed out:".bold()),
@@ -385,10 +436,183 @@ impl ResolvedArgs {
                     println!("{}", stat.format_table(false, build_stats));
                 }
             }
-        }
-        if self.json {
-            println!("{}", serde_json::to_string_pretty(&indexmap)?);
-        }
+            Ok(())
+        })?;
+        renderer.finish(&indexmap)?;
         Ok(true)
     }
+
+    /// Polls the builds of the evaluations for `--watch`.
+    pub(crate) fn watch_evaluations(&self, evals: &[Evaluation]) -> anyhow::Result<bool> {
+        let evals = self.resolve_evaluations(evals)?;
+        let fetch = |eval: &Evaluation| {
+            EvalReport::new(eval.clone(), &self.client).fetch_and_read(&self.client)
+        };
+        self.watch(|| {
+            let mut poll = Poll {
+                groups: vec![],
+                success: true,
+            };
+            fetch_in_order(&evals, self.jobs, fetch, |_, stat| {
+                let stat = stat?;
+                let heading = stat.heading();
+                let builds = [
+                    stat.aborted,
+                    stat.now_fail,
+                    stat.now_succeed,
+                    stat.new,
+                    stat.removed,
+                    stat.still_fail,
+                    stat.still_succeed,
+                    stat.unfinished,
+                ]
+                .concat();
+                poll.groups.push((heading, builds));
+                Ok(())
+            })?;
+            Ok(poll)
+        })
+    }
+}
+
+#[test]
+fn eval_report_urls() {
+    let client = HydraClient::new("https://hydra.nixos.org");
+    let eval = Evaluation::new(1_809_808, Some("hello"), true);
+    let stat = EvalReport::new(eval.clone(), &client);
+    assert_eq!(
+        stat.get_url(),
+        "https://hydra.nixos.org/eval/1809808?filter=hello&full=1"
+    );
+    let stat = EvalReport::new(eval.compared_to(1_809_585), &client);
+    assert_eq!(
+        stat.get_url(),
+        "https://hydra.nixos.org/eval/1809808?filter=hello&full=1&compare=1809585"
+    );
+}
+
+#[test]
+#[allow(clippy::too_many_lines)] // for the snapshot
+fn replay_eval() {
+    let client = crate::backends::replay_client();
+    let report = client.eval(1_809_808, None).unwrap();
+    assert_snapshot!(serde_json::to_string_pretty(&report).unwrap(), @r#"
+        {
+          "id": 1809808,
+          "filter": null,
+          "long": false,
+          "url": "https://hydra.nixos.org/eval/1809808",
+          "inputs": [
+            {
+              "name": "nixpkgs",
+              "type": "Git checkout",
+              "value": "https://github.com/nixos/nixpkgs.git",
+              "revision": "1e9e641a3fc1b22fbdb823a99d8ff96692cc4fba",
+              "store_path": "/nix/store/ln479gq56q3kyzyl0mm00xglpmfpzqx4-source"
+            },
+            {
+              "name": "officialRelease",
+              "type": "Boolean",
+              "value": "false"
+            }
+          ],
+          "changes": [
+            {
+              "input": "nixpkgs",
+              "description": "8c4dc69b9732 to 1e9e641a3fc1",
+              "url": "https://hydra.nixos.org/api/scmdiff?rev2=1e9e641a3fc1b22fbdb823a99d8ff96692cc4fba&uri=https%3A%2F%2Fgithub.com%2Fnixos%2Fnixpkgs.git&type=git&rev1=8c4dc69b9732f6bbe826b5fbb32184987520ff26&branch=",
+              "revs": [
+                "8c4dc69b9732f6bbe826b5fbb32184987520ff26",
+                "1e9e641a3fc1b22fbdb823a99d8ff96692cc4fba"
+              ],
+              "short_revs": [
+                "8c4dc69b9732",
+                "1e9e641a3fc1"
+              ]
+            }
+          ],
+          "aborted": [
+            {
+              "icon": "✖",
+              "success": false,
+              "status": "Timed out",
+              "timestamp": "2025-02-22T16:02:33Z",
+              "build_id": "290030811",
+              "build_url": "https://hydra.nixos.org/build/290030811",
+              "name": "libreoffice-24.8.4.2-wrapped",
+              "arch": "x86_64-linux",
+              "evals": true,
+              "job_name": "libreoffice.x86_64-linux"
+            }
+          ],
+          "now_fail": [
+            {
+              "icon": "✖",
+              "success": false,
+              "status": "Failed",
+              "timestamp": "2025-02-22T09:14:27Z",
+              "build_id": "290021987",
+              "build_url": "https://hydra.nixos.org/build/290021987",
+              "name": "python3.12-numpy-2.2.2",
+              "arch": "aarch64-linux",
+              "evals": true,
+              "job_name": "python312Packages.numpy.aarch64-linux"
+            }
+          ],
+          "now_succeed": [
+            {
+              "icon": "✔",
+              "success": true,
+              "status": "Succeeded",
+              "timestamp": "2025-02-22T08:01:12Z",
+              "build_id": "290019644",
+              "build_url": "https://hydra.nixos.org/build/290019644",
+              "name": "ripgrep-14.1.1",
+              "arch": "x86_64-darwin",
+              "evals": true,
+              "job_name": "ripgrep.x86_64-darwin"
+            }
+          ],
+          "new": [],
+          "removed": [
+            {
+              "icon": "⚠",
+              "success": false,
+              "status": "Removed",
+              "build_url": "https://hydra.nixos.org/job/nixpkgs/unstable/python38.x86_64-linux",
+              "arch": "x86_64-linux",
+              "evals": false,
+              "job_name": "python38.x86_64-linux"
+            }
+          ],
+          "still_fail": [],
+          "still_succeed": [
+            {
+              "icon": "✔",
+              "success": true,
+              "status": "Succeeded",
+              "timestamp": "2025-02-22T07:47:19Z",
+              "build_id": "290062156",
+              "build_url": "https://hydra.nixos.org/build/290062156",
+              "name": "hello-2.12.1",
+              "arch": "x86_64-linux",
+              "evals": true,
+              "job_name": "hello.x86_64-linux"
+            },
+            {
+              "icon": "✔",
+              "success": true,
+              "status": "Succeeded",
+              "timestamp": "2025-02-22T07:52:40Z",
+              "build_id": "290062157",
+              "build_url": "https://hydra.nixos.org/build/290062157",
+              "name": "hello-2.12.1",
+              "arch": "aarch64-linux",
+              "evals": true,
+              "job_name": "hello.aarch64-linux"
+            }
+          ],
+          "unfinished": []
+        }
+    "#);
 }
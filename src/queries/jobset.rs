@@ -4,10 +4,16 @@
 use anyhow::bail;
 use colored::Colorize;
 use indexmap::IndexMap;
+use scraper::ElementRef;
 
+#[cfg(test)]
+use insta::assert_snapshot;
+
+use super::watch::Poll;
 use crate::{
-    constants::HYDRA_CHECK_HOST_URL, is_skipable_row, EvalStatus, FetchHydraReport, ResolvedArgs,
-    SoupFind, StatusIcon, TryAttr,
+    is_skipable_row,
+    render::{OutputFormat, Renderer},
+    EvalStatus, FetchHydraReport, HydraClient, ResolvedArgs, SoupFind, StatusIcon, TryAttr,
 };
 
 #[derive(Clone)]
@@ -23,40 +29,40 @@ impl FetchHydraReport for JobsetReport<'_> {
     fn get_url(&self) -> &str {
         &self.url
     }
-
-    fn finish_with_error(self, status: String) -> Self {
-        Self {
-            evals: vec![EvalStatus {
-                icon: StatusIcon::Warning,
-                status,
-                ..Default::default()
-            }],
-            ..self
-        }
-    }
 }
 
 impl<'a> From<&'a ResolvedArgs> for JobsetReport<'a> {
     fn from(args: &'a ResolvedArgs) -> Self {
+        Self::new(&args.jobset, &args.client)
+    }
+}
+
+impl<'a> JobsetReport<'a> {
+    pub(crate) fn new(jobset: &'a str, client: &HydraClient) -> Self {
         //
         // https://hydra.nixos.org/jobset/nixpkgs/unstable/evals
         //
-        let url = format!("{}/jobset/{}/evals", &*HYDRA_CHECK_HOST_URL, args.jobset);
+        let url = format!("{}/jobset/{jobset}/evals", client.host_url());
         Self {
-            jobset: &args.jobset,
+            jobset,
             url,
             evals: vec![],
         }
     }
-}
 
-impl JobsetReport<'_> {
-    pub(crate) fn fetch_and_read(self) -> anyhow::Result<Self> {
-        let doc = self.fetch_document()?;
-        let tbody = match self.find_tbody(&doc, "") {
-            Err(stat) => return Ok(stat),
-            Ok(tbody) => tbody,
-        };
+    /// Fetches the evaluations from the web page of Hydra with any backend,
+    /// since the JSON API lacks their build statistics, without which the
+    /// unfinished evaluations cannot be told apart, e.g. for `--releases`.
+    pub(crate) fn fetch_and_read(self, client: &HydraClient) -> anyhow::Result<Self> {
+        let doc = client.backend().fetch_html(&self.url)?;
+        let tbody = self.find_tbody(&doc, "")?;
+        let evals = self
+            .read_evals(tbody)
+            .map_err(|err| self.parse_error(&err))?;
+        Ok(Self { evals, ..self })
+    }
+
+    fn read_evals(&self, tbody: ElementRef<'_>) -> anyhow::Result<Vec<EvalStatus>> {
         let mut evals: Vec<EvalStatus> = Vec::new();
         for row in tbody.find_all("tr") {
             let columns = row.find_all("td");
@@ -136,7 +142,7 @@ impl JobsetReport<'_> {
                 delta,
             });
         }
-        Ok(Self { evals, ..self })
+        Ok(evals)
     }
 }
 
@@ -151,15 +157,15 @@ impl ResolvedArgs {
         force_short_output: bool,
     ) -> anyhow::Result<Option<u64>> {
         let stat = JobsetReport::from(self);
-        let (short, json) = match force_short_output {
-            true => (true, false),
-            false => (self.short, self.json),
+        let (short, format) = match force_short_output {
+            true => (true, OutputFormat::Table),
+            false => (self.short, self.format),
         };
         if self.url {
             println!("{}", stat.get_url());
             return Ok(None);
         }
-        if !json {
+        if format.is_table() {
             // print title first, then fetch
             println!(
                 "Evaluations of jobset {} {}",
@@ -167,25 +173,118 @@ impl ResolvedArgs {
                 format!("@ {}", stat.get_url()).dimmed()
             );
         }
-        let stat = stat.fetch_and_read()?;
+        let stat = stat.fetch_and_read(&self.client)?;
         let first_stat = stat.evals.first();
         let latest_id = first_stat.and_then(|x| x.id);
-        if json {
-            let mut indexmap = IndexMap::new();
-            match short {
-                true => indexmap.insert(
-                    &stat.jobset,
-                    match first_stat {
-                        Some(x) => vec![x.to_owned()],
-                        None => vec![],
-                    },
-                ),
-                false => indexmap.insert(&stat.jobset, stat.evals),
+        if !format.is_table() {
+            let evals = match short {
+                true => first_stat.cloned().into_iter().collect(),
+                false => stat.evals.clone(),
             };
-            println!("{}", serde_json::to_string_pretty(&indexmap)?);
+            let mut renderer = Renderer::new(format);
+            for eval in &evals {
+                renderer.push(&[("jobset", stat.jobset)], eval)?;
+            }
+            renderer.push_jobset(stat.jobset, &stat.evals);
+            let mut indexmap = IndexMap::new();
+            indexmap.insert(&stat.jobset, evals);
+            renderer.finish(&indexmap)?;
             return Ok(latest_id);
         }
         println!("{}", stat.format_table(short, &stat.evals));
         Ok(latest_id)
     }
+
+    /// Pushes the latest evaluation of each of the `jobsets` to the
+    /// `renderer`, if it renders the Prometheus gauges.
+    pub(crate) fn push_latest_evals(
+        &self,
+        renderer: &mut Renderer,
+        jobsets: &[&str],
+    ) -> anyhow::Result<()> {
+        if self.format != OutputFormat::Prometheus {
+            return Ok(());
+        }
+        for jobset in jobsets {
+            let stat = JobsetReport::new(jobset, &self.client).fetch_and_read(&self.client)?;
+            renderer.push_jobset(jobset, &stat.evals);
+        }
+        Ok(())
+    }
+
+    /// Polls the evaluations of the jobset for `--watch`.
+    pub(crate) fn watch_jobset(&self) -> anyhow::Result<bool> {
+        self.watch(|| {
+            let mut stat = JobsetReport::from(self).fetch_and_read(&self.client)?;
+            if self.short {
+                stat.evals.truncate(1);
+            }
+            let heading = format!(
+                "Evaluations of jobset {} {}",
+                self.jobset.bold(),
+                format!("@ {}", stat.get_url()).dimmed()
+            );
+            Ok(Poll {
+                groups: vec![(heading, stat.evals)],
+                success: true,
+            })
+        })
+    }
+}
+
+#[test]
+fn replay_jobset_evals() {
+    let client = crate::backends::replay_client();
+    let evals = client.jobset_evals("nixpkgs/unstable").unwrap();
+    assert_snapshot!(serde_json::to_string_pretty(&evals).unwrap(), @r#"
+        [
+          {
+            "icon": "⧖",
+            "finished": false,
+            "id": 1812345,
+            "url": "https://hydra.nixos.org/eval/1812345",
+            "datetime": "2025-02-24T08:43:29Z",
+            "relative": "3h ago",
+            "timestamp": 1740386609,
+            "status": "",
+            "short_rev": "0196c01",
+            "input_changes": "nixpkgs → 0196c01",
+            "succeeded": 213804,
+            "failed": 5624,
+            "queued": 38201,
+            "delta": "+31"
+          },
+          {
+            "icon": "✔",
+            "finished": true,
+            "id": 1812301,
+            "url": "https://hydra.nixos.org/eval/1812301",
+            "datetime": "2025-02-23T20:12:03Z",
+            "relative": "16h ago",
+            "timestamp": 1740341523,
+            "status": "Evaluation error",
+            "short_rev": "8c4dc69",
+            "input_changes": "nixpkgs → 8c4dc69",
+            "succeeded": 213773,
+            "failed": 5631,
+            "queued": 0,
+            "delta": "-2"
+          },
+          {
+            "icon": "✔",
+            "finished": true,
+            "id": 1809808,
+            "url": "https://hydra.nixos.org/eval/1809808",
+            "datetime": "2025-02-22T04:30:51Z",
+            "relative": "2d ago",
+            "timestamp": 1740198651,
+            "status": "",
+            "short_rev": "1e9e641",
+            "input_changes": "nixpkgs → 1e9e641",
+            "succeeded": 213775,
+            "failed": 5629,
+            "queued": 0
+          }
+        ]
+    "#);
 }
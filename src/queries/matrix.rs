@@ -0,0 +1,144 @@
+//! A module that renders the latest build status of packages as a matrix,
+//! either on multiple architectures for `--arch all` or `--arch x,y,...`,
+//! or side by side across channels for `--compare-channels`.
+
+use colored::{ColoredString, Colorize};
+use indexmap::IndexMap;
+
+use super::PackageReport;
+use crate::{
+    error::{catch_missing, JsonResult},
+    fetch_in_order, format_rows,
+    render::Renderer,
+    BuildStatus, FetchHydraReport, ResolvedArgs, ShowHydraStatus, StatusIcon,
+};
+
+/// A row of the matrix, i.e. the header or the latest builds of a package
+struct MatrixRow(Vec<ColoredString>);
+
+impl ShowHydraStatus for MatrixRow {
+    fn format_as_vec(&self) -> Vec<ColoredString> {
+        self.0.clone()
+    }
+}
+
+/// Formats a cell of the matrix, with the name (version) and the date of
+/// the build if `details` are requested.
+fn format_cell(build: &JsonResult<BuildStatus>, details: bool) -> ColoredString {
+    let build = match build {
+        JsonResult::Ok(build) => build,
+        JsonResult::Err { error } => {
+            let icon = ColoredString::from(&StatusIcon::Warning);
+            return match details {
+                true => format!("{icon} {}", error.kind()).into(),
+                false => icon,
+            };
+        }
+    };
+    let icon = ColoredString::from(&build.icon);
+    if !details {
+        return icon;
+    }
+    if !build.evals {
+        // e.g. "Queued: no build has been attempted for this package yet"
+        let status = build.status.split(':').next().unwrap_or_default();
+        return format!("{icon} {status}").into();
+    }
+    let name = build.name.as_deref().unwrap_or_default();
+    let date = build
+        .timestamp
+        .as_deref()
+        .and_then(|x| x.split_once('T'))
+        .unwrap_or_default()
+        .0;
+    format!("{icon} {name} {}", date.dimmed()).into()
+}
+
+impl ResolvedArgs {
+    /// Fetches the latest build of each package for each of the `columns`,
+    /// i.e. pairs of a label (the arch or channel) and a jobset, where
+    /// `packages` pairs each package with its job names per column.
+    pub(crate) fn fetch_and_print_matrix(
+        &self,
+        packages: &[(String, Vec<String>)],
+        columns: &[(String, String)],
+        details: bool,
+    ) -> anyhow::Result<bool> {
+        let jobs: Vec<_> = packages
+            .iter()
+            .flat_map(|(package, jobs)| columns.iter().zip(jobs).map(move |x| (package, x)))
+            .collect();
+        if self.url {
+            for (_, ((_, jobset), job)) in &jobs {
+                let stat = PackageReport::new(job, jobset, &self.client, false);
+                println!("{}", stat.get_url());
+            }
+            return Ok(true);
+        }
+        let same_jobset = columns.iter().all(|(_, jobset)| *jobset == self.jobset);
+        if self.format.is_table() {
+            // print title first, then fetch
+            match same_jobset {
+                true => println!("Build Status on jobset {}", self.jobset.bold()),
+                false => println!("Build Status across channels"),
+            }
+        }
+        let mut status = true;
+        let mut matrix: IndexMap<&str, IndexMap<&str, JsonResult<BuildStatus>>> = IndexMap::new();
+        let mut renderer = Renderer::new(self.format);
+        // the details are only shown for --compare-channels
+        let column = match details {
+            true => "channel",
+            false => "arch",
+        };
+        let fetch = |(_, ((_, jobset), job)): &(&String, (&(String, String), &String))| {
+            let stat = PackageReport::new(job, jobset, &self.client, false);
+            catch_missing(stat.fetch_and_read(&self.client).map(|stat| stat.builds))
+        };
+        fetch_in_order(&jobs, self.jobs, fetch, |idx, stat| {
+            let (package, ((label, jobset), job)) = jobs[idx];
+            let stat = stat?;
+            let builds = match &stat {
+                Ok(builds) => Ok(builds.as_slice()),
+                Err(error) => Err(error),
+            };
+            renderer.push_job(jobset, job, builds);
+            // e.g. a package that is not available on all the architectures
+            let build = match stat {
+                Ok(builds) => JsonResult::Ok(builds.into_iter().next().unwrap_or_default()),
+                Err(error) => JsonResult::Err { error },
+            };
+            status &= matches!(&build, JsonResult::Ok(build) if build.success);
+            renderer.push(&[("package", package), (column, label)], &build)?;
+            matrix
+                .entry(package.as_str())
+                .or_default()
+                .insert(label.as_str(), build);
+            Ok(())
+        })?;
+        if !self.format.is_table() {
+            let mut jobsets = Vec::new();
+            for (_, jobset) in columns {
+                if !jobsets.contains(&jobset.as_str()) {
+                    jobsets.push(jobset.as_str());
+                }
+            }
+            self.push_latest_evals(&mut renderer, &jobsets)?;
+            renderer.finish(&matrix)?;
+            return Ok(status);
+        }
+        let header: Vec<_> = columns.iter().map(|(label, _)| label.bold()).collect();
+        let mut rows = vec![MatrixRow([vec!["".into()], header].concat())];
+        if !same_jobset {
+            let jobsets: Vec<_> = columns.iter().map(|(_, jobset)| jobset.dimmed()).collect();
+            rows.push(MatrixRow([vec!["".into()], jobsets].concat()));
+        }
+        for (package, builds) in &matrix {
+            let mut row = vec![(*package).into()];
+            row.extend(builds.values().map(|build| format_cell(build, details)));
+            rows.push(MatrixRow(row));
+        }
+        println!("{}", format_rows(false, &rows));
+        Ok(status)
+    }
+}
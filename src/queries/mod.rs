@@ -1,4 +1,15 @@
+mod bisect;
 mod builds;
+mod common_good;
+mod contains;
 mod evals;
 mod jobset;
+mod matrix;
 mod packages;
+mod watch;
+
+pub use builds::{BuildProduct, BuildReport};
+pub(crate) use contains::DEFAULT_CHANNELS;
+pub use evals::{EvalInputChanges, EvalReport};
+pub(crate) use jobset::JobsetReport;
+pub(crate) use packages::PackageReport;
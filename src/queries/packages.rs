@@ -1,49 +1,53 @@
 //! A module that formats the details of the specified (or inferred) packages,
 //! e.g. from <https://hydra.nixos.org/job/nixpkgs/unstable/hello.x86_64-linux>.
 
+use anyhow::anyhow;
 use colored::Colorize;
 use indexmap::IndexMap;
 use log::{info, warn};
-use std::collections::VecDeque;
 
-use super::builds::BuildReport;
+#[cfg(test)]
+use insta::assert_snapshot;
+
+use super::{
+    builds::{fetch_log_tail, BuildReport},
+    watch::Poll,
+};
 use crate::{
-    constants,
+    backends::HydraDocument,
+    error::{catch_missing, JsonResult, Reported},
+    fetch_in_order,
     queries::jobset::JobsetReport,
-    structs::{BuildStatus, EvalStatus, ReleaseStatus},
-    FetchHydraReport, ResolvedArgs, StatusIcon,
+    render::Renderer,
+    structs::{BuildStatus, ReleaseStatus},
+    FetchHydraReport, HydraClient, HydraError, ResolvedArgs, StatusIcon,
 };
 
 #[derive(Clone)]
 /// Container for the build status and metadata of a package
-struct PackageReport<'a> {
+pub(crate) struct PackageReport<'a> {
     package: &'a str,
     url: String,
     /// Status of recent builds of the package
-    builds: Vec<BuildStatus>,
+    pub(crate) builds: Vec<BuildStatus>,
 }
 
 impl FetchHydraReport for PackageReport<'_> {
     fn get_url(&self) -> &str {
         &self.url
     }
-
-    fn finish_with_error(self, status: String) -> Self {
-        Self {
-            builds: vec![BuildStatus {
-                icon: StatusIcon::Warning,
-                status,
-                ..Default::default()
-            }],
-            ..self
-        }
-    }
 }
 
 impl<'a> PackageReport<'a> {
     /// Initializes the status container with the resolved package name
     /// and the resolved command line arguments.
     fn from_package_with_args(package: &'a str, args: &'a ResolvedArgs) -> Self {
+        Self::new(package, &args.jobset, &args.client, args.long)
+    }
+
+    /// Initializes the status container for a `package` in a `jobset`,
+    /// optionally with all of its builds (`long`).
+    pub(crate) fn new(package: &'a str, jobset: &str, client: &HydraClient, long: bool) -> Self {
         //
         // Examples:
         // - https://hydra.nixos.org/job/nixos/release-19.09/nixpkgs.hello.x86_64-linux/latest
@@ -53,10 +57,9 @@ impl<'a> PackageReport<'a> {
         // There is also {url}/all which is a lot slower.
         //
         let url = format!(
-            "{}/job/{}/{package}{}",
-            &*constants::HYDRA_CHECK_HOST_URL,
-            args.jobset,
-            if args.long { "/all" } else { "" }
+            "{}/job/{jobset}/{package}{}",
+            client.host_url(),
+            if long { "/all" } else { "" }
         );
         Self {
             package,
@@ -65,130 +68,247 @@ impl<'a> PackageReport<'a> {
         }
     }
 
-    fn fetch_and_read(self) -> anyhow::Result<Self> {
-        let doc = self.fetch_document()?;
-        let tbody = match self.find_tbody(&doc, "") {
-            Err(stat) => return Ok(stat),
-            Ok(tbody) => tbody,
+    pub(crate) fn fetch_and_read(self, client: &HydraClient) -> anyhow::Result<Self> {
+        let doc = match self.fetch_document(client.backend())? {
+            HydraDocument::Json(json) => {
+                let err = || anyhow!("could not parse the builds from: {json}");
+                let builds = json
+                    .as_array()
+                    .ok_or_else(err)
+                    .and_then(|builds| {
+                        builds
+                            .iter()
+                            .map(|build| BuildStatus::from_json(build, client.host_url()))
+                            .collect::<anyhow::Result<_>>()
+                    })
+                    .map_err(|err| self.parse_error(&err))?;
+                return Ok(Self { builds, ..self });
+            }
+            HydraDocument::Html(doc) => doc,
         };
-        let builds = BuildStatus::from_tbody(tbody)?;
+        let tbody = self.find_tbody(&doc, "")?;
+        let builds = BuildStatus::from_tbody(tbody).map_err(|err| self.parse_error(&err))?;
         Ok(Self { builds, ..self })
     }
 }
 
+/// Fetched reports for a single package, ready to be printed
+struct FetchedPackage<'a> {
+    stat: PackageReport<'a>,
+    success: bool,
+    release_stats: Vec<ReleaseStatus>,
+    /// Inputs of the latest success from a finished eval, if the package fails
+    latest_finished: Option<BuildReport>,
+    /// URL of the log that is attached to the latest failed build, with `--log`
+    log_url: Option<String>,
+    /// Why the package could not be found, if so
+    error: Option<HydraError>,
+}
+
 impl ResolvedArgs {
+    /// Fetches all the reports of a single package, possibly on a worker
+    /// thread, without printing anything to stdout.
+    fn fetch_package<'a>(&'a self, package: &'a str) -> anyhow::Result<FetchedPackage<'a>> {
+        let stat = PackageReport::from_package_with_args(package, self);
+        let jobset = self.jobset.as_str();
+        let jobset_report = if self.releases {
+            info!("fetching recent evals on --jobset {jobset} for --releases");
+            let jobset_report = JobsetReport::from(self).fetch_and_read(&self.client)?;
+            eprintln!();
+            Some(jobset_report)
+        } else {
+            None
+        };
+        let mut stat = match catch_missing(stat.clone().fetch_and_read(&self.client))? {
+            Ok(stat) => stat,
+            Err(error) => {
+                return Ok(FetchedPackage {
+                    stat,
+                    success: false,
+                    release_stats: vec![],
+                    latest_finished: None,
+                    log_url: None,
+                    error: Some(error),
+                })
+            }
+        };
+        let success = stat.builds.first().is_some_and(|build| build.success);
+        let log_url = self.explain_failure(&mut stat.builds);
+        let release_stats = if let Some(jobset_report) = jobset_report {
+            let channel = self.channel.as_deref().unwrap_or_else(|| {
+                warn!("--channel is not set, so we could not link to releases.nixos.org");
+                "" // set to empty string for ease of use below
+            });
+            ReleaseStatus::join(
+                jobset_report.evals,
+                &stat.builds,
+                channel,
+                jobset,
+                &self.client,
+            )
+        } else {
+            vec![]
+        };
+        let latest_finished = if !success && self.format.is_table() {
+            let url_stripped = stat.get_url().trim_end_matches("/all");
+            let url = format!("{url_stripped}/latest-finished");
+            // e.g. there is no success at all
+            BuildReport::from_url(&url)
+                .fetch_and_read(&self.client)
+                .inspect_err(|err| warn!("{err}"))
+                .ok()
+        } else {
+            None
+        };
+        Ok(FetchedPackage {
+            stat,
+            success,
+            release_stats,
+            latest_finished,
+            log_url,
+            error: None,
+        })
+    }
+
+    /// Resolves the `FailureCause` of the latest build among `builds` if it
+    /// has failed, and attaches its log tail with `--log`, returning the URL
+    /// of the log. Errors are only reported as warnings, since neither of
+    /// them is essential to the build status.
+    fn explain_failure(&self, builds: &mut [BuildStatus]) -> Option<String> {
+        let build = builds
+            .first_mut()
+            .filter(|build| build.evals && build.icon == StatusIcon::Failed)?;
+        let build_url = build.build_url.clone()?;
+        let report = BuildReport::from_url(&build_url);
+        if build.status == "Dependency failed" {
+            build.cause = report
+                .find_failure_cause(&self.client)
+                .unwrap_or_else(|err| {
+                    warn!("could not find the failed dependency of {build_url}: {err}");
+                    None
+                });
+        }
+        let lines = self.log?;
+        let log_url = match build.cause.as_ref().and_then(|x| x.log_url.clone()) {
+            Some(url) => url,
+            None => format!("{build_url}/log"),
+        };
+        match fetch_log_tail(&self.client, &log_url, lines) {
+            Ok(tail) => {
+                build.log_tail = Some(tail);
+                Some(log_url)
+            }
+            Err(err) => {
+                warn!("could not fetch the log of {build_url}: {err}");
+                None
+            }
+        }
+    }
+
     #[allow(clippy::too_many_lines)]
-    pub(crate) fn fetch_and_print_packages(&self, packages: &[String]) -> anyhow::Result<bool> {
+    pub(crate) fn fetch_and_print_packages<'a>(
+        &'a self,
+        packages: &'a [String],
+    ) -> anyhow::Result<bool> {
+        if self.url {
+            for package in packages {
+                let stat = PackageReport::from_package_with_args(package, self);
+                println!("{}", stat.get_url());
+            }
+            return Ok(true);
+        }
         let mut status = true;
+        let mut first_error = None;
         let mut all_builds = IndexMap::new();
         let mut all_releases = IndexMap::new();
-        for (idx, package) in packages.iter().enumerate() {
-            // postpone fetching until after the title is printed
-            let stat = PackageReport::from_package_with_args(package, self);
-            if self.url {
-                println!("{}", stat.get_url());
-                continue;
-            }
+        let mut renderer = Renderer::new(self.format);
+        let jobset = self.jobset.as_str();
+        let fetch = |package: &'a String| self.fetch_package(package);
+        fetch_in_order(packages, self.jobs, fetch, |idx, fetched| {
+            let FetchedPackage {
+                stat,
+                success,
+                release_stats,
+                latest_finished,
+                log_url,
+                error,
+            } = fetched?;
             let url_dimmed = stat.get_url().dimmed();
-            let jobset = self.jobset.as_str();
-            let jobset_report = if self.releases {
-                info!("fetching recent evals on --jobset {jobset} for --releases");
-                let jobset_report = JobsetReport::from(self).fetch_and_read()?;
-                eprintln!();
-                Some(jobset_report)
-            } else {
-                None
-            };
-            if !self.json {
-                // print title first, then fetch
-                if idx > 0 && !self.short {
-                    println!(); // vertical whitespace
-                }
-                println!(
-                    "Build Status for {} on jobset {}",
-                    stat.package.bold(),
-                    jobset.bold(),
-                );
-                if !self.short {
-                    println!("{url_dimmed}");
-                }
-            }
-            let stat = stat.fetch_and_read()?;
-            let first_stat = stat.builds.first();
-            let success = first_stat.is_some_and(|build| build.success);
             if !success {
                 status = false;
             }
-            let release_stats = if let Some(jobset_report) = jobset_report {
-                // mutable refs that is quick to remove from the front
-                let mut test_builds: VecDeque<&BuildStatus> = stat.builds.iter().collect();
-
-                // if _all_ evals appear to be unfinished, it's likely that the
-                // instance is being rebooted, and we will always link to the
-                // releases as it's more practical
-                let always_link = jobset_report
-                    .evals
-                    .iter()
-                    .all(|eval| !eval.finished.unwrap_or_default());
-                let channel = self.channel.as_deref().unwrap_or_else(|| {
-                    warn!("--channel is not set, so we could not link to releases.nixos.org");
-                    "" // set to empty string for ease of use below
-                });
-
-                // this captures `test_builds` mutably but it does _not_ need
-                // to be marked as `mut` because it is moved into .filter_map()
-                // and re-borrowed as mut by them.
-                let filter_eval = |eval: EvalStatus| {
-                    let short_rev = eval.short_rev.as_deref().unwrap_or_default();
-                    for index in 0..test_builds.len() {
-                        if test_builds[index]
-                            .name
-                            .as_deref()
-                            .unwrap_or_default()
-                            .contains(short_rev)
-                        {
-                            let test = test_builds.remove(index)?.clone();
-                            return Some(ReleaseStatus::new(
-                                eval,
-                                test,
-                                channel,
-                                jobset,
-                                always_link,
-                            ));
-                        }
-                    }
-                    None
+            if let Some(error) = &error {
+                first_error.get_or_insert(error.clone());
+            }
+            if !self.format.is_table() {
+                let builds = match &error {
+                    Some(error) => Err(error),
+                    None => Ok(stat.builds.as_slice()),
                 };
-                jobset_report
-                    .evals
-                    .into_iter()
-                    .filter_map(filter_eval)
-                    .collect()
-            } else {
-                vec![]
-            };
-            if self.json {
-                if self.releases {
+                renderer.push_job(jobset, stat.package, builds);
+                let context = match self.releases {
+                    true => [("jobset", jobset)],
+                    false => [("package", stat.package)],
+                };
+                if let Some(error) = error {
+                    renderer.push(
+                        &context,
+                        &JsonResult::<()>::Err {
+                            error: error.clone(),
+                        },
+                    )?;
+                    if self.releases {
+                        all_releases.insert(jobset, JsonResult::Err { error });
+                    } else {
+                        all_builds.insert(stat.package, JsonResult::Err { error });
+                    }
+                } else if self.releases {
                     let release_stats = match self.short {
                         true => release_stats.first().cloned().into_iter().collect(),
                         false => release_stats,
                     };
-                    all_releases.insert(jobset, release_stats);
+                    for release in &release_stats {
+                        renderer.push(&context, release)?;
+                    }
+                    all_releases.insert(jobset, JsonResult::Ok(release_stats));
                 } else {
                     let build_stats = match self.short {
-                        true => first_stat.cloned().into_iter().collect(),
+                        true => stat.builds.first().cloned().into_iter().collect(),
                         false => stat.builds,
                     };
-                    all_builds.insert(stat.package, build_stats);
+                    for build in &build_stats {
+                        renderer.push(&context, build)?;
+                    }
+                    all_builds.insert(stat.package, JsonResult::Ok(build_stats));
                 }
-                continue; // print later
+                return Ok(()); // print later
+            }
+            if idx > 0 && !self.short {
+                println!(); // vertical whitespace
+            }
+            println!(
+                "Build Status for {} on jobset {}",
+                stat.package.bold(),
+                jobset.bold(),
+            );
+            if !self.short {
+                println!("{url_dimmed}");
+            }
+            if let Some(error) = error {
+                println!("{} {error}", StatusIcon::Warning);
+                return Ok(());
             }
             match self.releases {
                 true => println!("{}", stat.format_table(self.short, &release_stats)),
                 false => println!("{}", stat.format_table(self.short, &stat.builds)),
             }
+            let log = stat.builds.iter().find_map(|build| build.log_tail.as_ref());
+            if let (Some(log_url), Some(log)) = (log_url, log) {
+                println!("\n{} {}", "Log:".bold(), log_url.dimmed());
+                println!("{log}");
+            }
             let url_stripped = stat.get_url().trim_end_matches("/all");
-            if !success {
+            if let Some(build_report) = latest_finished {
                 if self.short {
                     info!("latest build failed, check out: {url_dimmed}");
                 } else {
@@ -211,8 +331,6 @@ impl ResolvedArgs {
                 }
                 info!("showing inputs for the latest success from a finished eval...");
 
-                let url = format!("{url_stripped}/latest-finished");
-                let build_report = BuildReport::from_url(&url).fetch_and_read()?;
                 for entry in &build_report.inputs {
                     if self.short {
                         if let (Some(name), Some(rev)) = (&entry.name, &entry.revision) {
@@ -224,13 +342,101 @@ impl ResolvedArgs {
                     }
                 }
             }
+            Ok(())
+        })?;
+        self.push_latest_evals(&mut renderer, &[jobset])?;
+        match self.releases {
+            true => renderer.finish(&all_releases)?,
+            false => renderer.finish(&all_builds)?,
         }
-        if self.json {
-            match self.releases {
-                true => println!("{}", serde_json::to_string_pretty(&all_releases)?),
-                false => println!("{}", serde_json::to_string_pretty(&all_builds)?),
-            }
+        if let Some(error) = first_error {
+            return Err(anyhow::Error::new(error).context(Reported));
         }
         Ok(status)
     }
+
+    /// Polls the builds of the `packages` for `--watch`.
+    pub(crate) fn watch_packages<'a>(&'a self, packages: &'a [String]) -> anyhow::Result<bool> {
+        let fetch = |package: &'a String| {
+            PackageReport::from_package_with_args(package, self).fetch_and_read(&self.client)
+        };
+        self.watch(|| {
+            let mut poll = Poll {
+                groups: vec![],
+                success: true,
+            };
+            fetch_in_order(packages, self.jobs, fetch, |_, stat| {
+                let mut stat = stat?;
+                poll.success &= stat.builds.first().is_some_and(|build| build.success);
+                if self.short {
+                    stat.builds.truncate(1);
+                }
+                let heading = format!(
+                    "Build Status for {} on jobset {}",
+                    stat.package.bold(),
+                    self.jobset.bold(),
+                );
+                poll.groups.push((heading, stat.builds));
+                Ok(())
+            })?;
+            Ok(poll)
+        })
+    }
+}
+
+#[test]
+fn replay_job_builds() {
+    let client = crate::backends::replay_client();
+    let builds = client
+        .job_builds("nixpkgs/unstable", "hello.x86_64-linux")
+        .unwrap();
+    assert_snapshot!(serde_json::to_string_pretty(&builds).unwrap(), @r#"
+        [
+          {
+            "icon": "⧖",
+            "success": false,
+            "status": "Queued: no build has been attempted for this package yet (still queued)",
+            "evals": false
+          },
+          {
+            "icon": "✔",
+            "success": true,
+            "status": "Succeeded",
+            "timestamp": "2025-02-22T07:47:19Z",
+            "build_id": "290062156",
+            "build_url": "https://hydra.nixos.org/build/290062156",
+            "name": "hello-2.12.1",
+            "arch": "x86_64-linux",
+            "evals": true
+          },
+          {
+            "icon": "✖",
+            "success": false,
+            "status": "Dependency failed",
+            "timestamp": "2025-02-20T13:05:41Z",
+            "build_id": "289870122",
+            "build_url": "https://hydra.nixos.org/build/289870122",
+            "name": "hello-2.12.1",
+            "arch": "x86_64-linux",
+            "evals": true
+          },
+          {
+            "icon": "✔",
+            "success": true,
+            "status": "Succeeded",
+            "timestamp": "2025-02-17T02:11:08Z",
+            "build_id": "289504237",
+            "build_url": "https://hydra.nixos.org/build/289504237",
+            "name": "hello-2.12.1",
+            "arch": "x86_64-linux",
+            "evals": true
+          }
+        ]
+    "#);
+    let err = client
+        .job_builds("nixpkgs/unstable", "discord.x86_64-linux")
+        .unwrap_err();
+    let err = err.downcast_ref::<HydraError>().unwrap();
+    assert_eq!(err.kind(), "not-evaluated");
+    assert_snapshot!(err, @"This job is not a member of the latest evaluation of its jobset. This means it was removed or had an evaluation error.");
 }
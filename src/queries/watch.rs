@@ -0,0 +1,133 @@
+//! A module that polls the reports of packages, jobsets or evaluations
+//! until nothing is queued anymore, for `--watch`.
+//!
+//! The first poll prints all the rows, and the following polls only print
+//! the rows whose status has changed since then.
+
+use log::info;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    format_rows, BuildStatus, EvalStatus, HydraError, ResolvedArgs, ShowHydraStatus, StatusIcon,
+};
+
+/// Trait for the entries of a report that are compared across polls.
+pub(super) trait WatchStatus: ShowHydraStatus {
+    /// Identifies the entry across polls, e.g. with its URL.
+    fn watch_key(&self) -> String;
+
+    /// Summary of the status; the entry is printed again when this changes.
+    fn watch_state(&self) -> String;
+
+    /// Whether the status of the entry may still change on Hydra.
+    fn is_pending(&self) -> bool;
+}
+
+impl WatchStatus for BuildStatus {
+    fn watch_key(&self) -> String {
+        self.build_url
+            .clone()
+            .unwrap_or_else(|| self.status.clone())
+    }
+
+    fn watch_state(&self) -> String {
+        format!("{} {}", self.icon, self.status)
+    }
+
+    fn is_pending(&self) -> bool {
+        self.icon == StatusIcon::Queued
+    }
+}
+
+impl WatchStatus for EvalStatus {
+    fn watch_key(&self) -> String {
+        self.id
+            .map_or_else(|| self.status.clone(), |id| id.to_string())
+    }
+
+    fn watch_state(&self) -> String {
+        // the relative time (e.g. `5h ago`) is left out on purpose
+        format!(
+            "{} {} {:?} {:?} {:?}",
+            self.icon, self.status, self.succeeded, self.failed, self.queued
+        )
+    }
+
+    fn is_pending(&self) -> bool {
+        match self.finished {
+            Some(finished) => !finished,
+            // e.g. without the build statistics on the page
+            None => self.queued.is_some_and(|x| x > 0),
+        }
+    }
+}
+
+/// Entries from a single poll, grouped under the headings they are printed
+/// with, along with the success code that the program would exit with.
+pub(super) struct Poll<T> {
+    pub(super) groups: Vec<(String, Vec<T>)>,
+    pub(super) success: bool,
+}
+
+impl ResolvedArgs {
+    /// Calls `poll` every `--interval` and prints the changed entries,
+    /// until none of them is pending or the `--timeout` is reached.
+    pub(super) fn watch<T: WatchStatus>(
+        &self,
+        mut poll: impl FnMut() -> anyhow::Result<Poll<T>>,
+    ) -> anyhow::Result<bool> {
+        info!(
+            "watching every {}s until nothing is queued",
+            self.watch_interval.as_secs()
+        );
+        let started = Instant::now();
+        let mut states = HashMap::new();
+        loop {
+            let Poll { groups, success } = poll()?;
+            let mut pending = false;
+            for (heading, entries) in groups {
+                let mut changed: Vec<T> = Vec::new();
+                for entry in entries {
+                    pending |= entry.is_pending();
+                    let key = format!("{heading} {}", entry.watch_key());
+                    let state = entry.watch_state();
+                    if states.insert(key, state.clone()) != Some(state) {
+                        changed.push(entry);
+                    }
+                }
+                if !changed.is_empty() {
+                    println!("{heading}");
+                    println!("{}\n", format_rows(false, &changed));
+                }
+            }
+            if !pending {
+                return Ok(success);
+            }
+            let remaining = match self.watch_timeout {
+                Some(timeout) => match timeout.checked_sub(started.elapsed()) {
+                    Some(remaining) if !remaining.is_zero() => remaining,
+                    _ => return Err(HydraError::WatchTimeout { timeout }.into()),
+                },
+                None => Duration::MAX,
+            };
+            std::thread::sleep(self.watch_interval.min(remaining));
+        }
+    }
+}
+
+#[test]
+fn pending_evals() {
+    let eval = |finished, queued| EvalStatus {
+        finished,
+        queued,
+        ..Default::default()
+    };
+    assert!(eval(Some(false), Some(3)).is_pending());
+    assert!(!eval(Some(true), Some(0)).is_pending());
+    assert!(eval(None, Some(3)).is_pending());
+    assert!(!eval(None, Some(0)).is_pending());
+    assert!(!eval(None, None).is_pending());
+}
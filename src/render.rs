@@ -0,0 +1,882 @@
+//! Renderers of the results for `--format`, besides the default tables.
+//!
+//! The entries of a report, e.g. the builds of a package or the evaluations
+//! of a jobset, are pushed to a [`Renderer`] as soon as they are fetched,
+//! along with their context, e.g. the package. They are then printed as:
+//!
+//! - JSON Lines right away, one object per entry with the context merged in,
+//!   so that e.g. `jq` can start before the run ends;
+//! - a Markdown table for each group of entries, under a heading from the
+//!   first item of the context, e.g. for pasting into GitHub issues;
+//! - a single CSV table at the end, with the nested fields flattened into
+//!   columns such as `cause.name`, e.g. for spreadsheets.
+//!
+//! JSON and YAML print the whole report at the end, as a single document.
+//!
+//! JUnit XML is only supported for the packages, whose latest builds are
+//! pushed as test cases with [`Renderer::push_job`], so that CI pipelines
+//! (e.g. GitLab or Jenkins) can show the health of the packages on Hydra.
+//!
+//! Prometheus gauges are likewise supported for the packages and jobsets,
+//! from the builds of each job and the latest evaluation of each jobset
+//! with [`Renderer::push_jobset`]. They are printed in the OpenMetrics text
+//! format, e.g. for the textfile collector of node_exporter, so that the
+//! monitoring can alert when a package has been failing for too long. The
+//! exit code is then 0, as the failures are in the gauges.
+
+use clap::ValueEnum;
+use indexmap::IndexMap;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::sync::LazyLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{backends::unix_from_iso8601, BuildStatus, EvalStatus, HydraError, StatusIcon};
+
+#[cfg(test)]
+use insta::assert_snapshot;
+
+/// Output formats of the results
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum OutputFormat {
+    /// Human readable tables
+    #[default]
+    Table,
+    /// JSON, same as --json
+    Json,
+    /// JSON Lines, one object per build or evaluation as soon as it is fetched
+    Jsonl,
+    /// CSV with a header row, e.g. for spreadsheets
+    Csv,
+    /// Markdown tables, e.g. for GitHub issues
+    Markdown,
+    /// YAML, with the same structure as JSON
+    Yaml,
+    /// JUnit XML, with a test case for each package (and arch), e.g. for CI
+    Junit,
+    /// OpenMetrics gauges, e.g. for the textfile collector of node_exporter
+    Prometheus,
+}
+
+impl OutputFormat {
+    /// Whether the results are printed as human readable tables.
+    pub(crate) fn is_table(self) -> bool {
+        self == Self::Table
+    }
+}
+
+/// Flattened fields of an entry, pairs of column names and plain values
+type Record = Vec<(String, String)>;
+
+/// A test case of the JUnit report, i.e. the latest build of a job
+#[derive(Debug)]
+struct TestCase {
+    /// Jobset of the job, e.g. `nixpkgs/unstable`
+    suite: String,
+    /// Name of the job, e.g. `hello.x86_64-linux`
+    name: String,
+    /// Outcome of the test case, unless it has passed
+    outcome: Option<Outcome>,
+}
+
+/// Outcome of a [`TestCase`] that has not passed
+#[derive(Debug)]
+struct Outcome {
+    /// Name of the element, i.e. `failure`, `skipped` or `error`
+    element: &'static str,
+    /// Status text of the build, or the kind of the error
+    kind: String,
+    message: String,
+    /// Build URL, failure cause and log tail of the build
+    details: String,
+}
+
+/// Names and help texts of the Prometheus metrics, in the printed order
+const METRICS: &[(&str, &str)] = &[
+    (
+        "hydra_check_job_success",
+        "Whether the latest build of the job has succeeded",
+    ),
+    (
+        "hydra_check_job_build_timestamp_seconds",
+        "Time of the latest build of the job",
+    ),
+    (
+        "hydra_check_job_last_success_timestamp_seconds",
+        "Time of the latest successful build of the job, among the fetched builds",
+    ),
+    (
+        "hydra_check_job_error",
+        "Whether the job could not be checked, e.g. as it is not evaluated",
+    ),
+    (
+        "hydra_check_jobset_builds",
+        "Number of builds of the latest evaluation of the jobset, by status",
+    ),
+    (
+        "hydra_check_jobset_latest_eval_id",
+        "ID of the latest evaluation of the jobset",
+    ),
+    (
+        "hydra_check_jobset_latest_eval_timestamp_seconds",
+        "Time of the latest evaluation of the jobset",
+    ),
+    (
+        "hydra_check_jobset_latest_eval_age_seconds",
+        "Seconds since the latest evaluation of the jobset",
+    ),
+];
+
+/// ANSI escape sequences, e.g. the colors of nix's error messages
+static ANSI_ESCAPE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\x1b\[[0-9;]*[A-Za-z]").unwrap());
+
+/// A sample of a Prometheus gauge, one of the [`METRICS`]
+#[derive(Debug)]
+struct Sample {
+    name: &'static str,
+    labels: Vec<(&'static str, String)>,
+    value: u64,
+}
+
+impl Outcome {
+    /// Classifies the latest `build` of a job: a failed build is a
+    /// `failure`, while a queued or cancelled build is `skipped`. A build
+    /// of a removed job is `skipped` as well: the job is gone from the
+    /// latest evaluation rather than failing in it, and `error` is kept
+    /// for the jobs that could not be checked at all.
+    fn from_build(build: Option<&BuildStatus>) -> Option<Self> {
+        let Some(build) = build else {
+            return Some(Self {
+                element: "skipped",
+                kind: String::new(),
+                message: "no builds of the job".into(),
+                details: String::new(),
+            });
+        };
+        if build.success {
+            return None;
+        }
+        let mut details = Vec::new();
+        if let Some(url) = &build.build_url {
+            details.push(format!("build: {url}"));
+        }
+        if let Some(cause) = &build.cause {
+            let name = cause.name.as_deref().unwrap_or("unknown derivation");
+            let url = cause.build_url.as_deref().unwrap_or_default();
+            details.push(format!("cause: {name} ({}) {url}", cause.status));
+        }
+        if let Some(log) = &build.log_tail {
+            let log = ANSI_ESCAPE.replace_all(log, "");
+            details.push(format!("log:\n{log}"));
+        }
+        Some(Self {
+            element: match build.icon {
+                StatusIcon::Failed => "failure",
+                _ => "skipped",
+            },
+            kind: build.status.clone(),
+            message: build.status.clone(),
+            details: details.join("\n"),
+        })
+    }
+}
+
+/// Prints the entries of a report in an [`OutputFormat`], see the
+/// [module documentation](self).
+#[derive(Debug)]
+pub(crate) struct Renderer {
+    format: OutputFormat,
+    /// Heading of the current group of Markdown records
+    heading: Option<String>,
+    /// Records of the current group for Markdown, or of all groups for CSV
+    records: Vec<Record>,
+    /// Number of Markdown groups printed so far
+    groups: usize,
+    /// Test cases of the JUnit report
+    cases: Vec<TestCase>,
+    /// Samples of the Prometheus gauges
+    samples: Vec<Sample>,
+}
+
+impl Renderer {
+    pub(crate) fn new(format: OutputFormat) -> Self {
+        Self {
+            format,
+            heading: None,
+            records: Vec::new(),
+            groups: 0,
+            cases: Vec::new(),
+            samples: Vec::new(),
+        }
+    }
+
+    /// Adds an `entry` of a report, e.g. a build, along with its `context`,
+    /// e.g. the package. The fields of the entry take precedence over the
+    /// context if they share a name.
+    pub(crate) fn push(
+        &mut self,
+        context: &[(&str, &str)],
+        entry: &impl Serialize,
+    ) -> anyhow::Result<()> {
+        let markdown = self.format == OutputFormat::Markdown;
+        let mut record = Map::new();
+        // the first item of the context is the heading in Markdown
+        for (key, value) in context.iter().skip(usize::from(markdown)) {
+            record.insert((*key).into(), (*value).into());
+        }
+        match serde_json::to_value(entry)? {
+            Value::Object(fields) => record.extend(fields),
+            Value::Null => {}
+            value => {
+                record.insert("value".into(), value);
+            }
+        }
+        match self.format {
+            OutputFormat::Jsonl => println!("{}", serde_json::to_string(&record)?),
+            OutputFormat::Markdown => {
+                let heading = context.first().map(|(_, value)| (*value).to_string());
+                if heading != self.heading {
+                    self.flush_group();
+                    self.heading = heading;
+                }
+                self.records.push(flatten(&Value::Object(record)));
+            }
+            OutputFormat::Csv => self.records.push(flatten(&Value::Object(record))),
+            OutputFormat::Table
+            | OutputFormat::Json
+            | OutputFormat::Yaml
+            | OutputFormat::Junit
+            | OutputFormat::Prometheus => {}
+        }
+        Ok(())
+    }
+
+    /// Adds the `builds` of a `job` in a `jobset`, latest first, as a test
+    /// case of the JUnit report or as the Prometheus gauges of the job, or
+    /// the error why the job could not be found.
+    pub(crate) fn push_job(
+        &mut self,
+        jobset: &str,
+        job: &str,
+        builds: Result<&[BuildStatus], &HydraError>,
+    ) {
+        if self.format == OutputFormat::Prometheus {
+            self.push_job_samples(jobset, job, builds);
+            return;
+        }
+        if self.format != OutputFormat::Junit {
+            return;
+        }
+        let outcome = match builds {
+            Ok(builds) => Outcome::from_build(builds.first()),
+            Err(error) => Some(Outcome {
+                element: "error",
+                kind: error.kind().into(),
+                message: error.to_string(),
+                details: String::new(),
+            }),
+        };
+        self.cases.push(TestCase {
+            suite: jobset.into(),
+            name: job.into(),
+            outcome,
+        });
+    }
+
+    fn push_job_samples(
+        &mut self,
+        jobset: &str,
+        job: &str,
+        builds: Result<&[BuildStatus], &HydraError>,
+    ) {
+        let labels = vec![("jobset", jobset.to_string()), ("job", job.to_string())];
+        let mut sample = |name, labels: &[(&'static str, String)], value| {
+            let labels = labels.to_vec();
+            self.samples.push(Sample {
+                name,
+                labels,
+                value,
+            });
+        };
+        let builds = match builds {
+            Ok(builds) => builds,
+            Err(error) => {
+                let labels = [labels, vec![("kind", error.kind().to_string())]].concat();
+                sample("hydra_check_job_error", &labels, 1);
+                return;
+            }
+        };
+        let timestamp = |build: &BuildStatus| unix_from_iso8601(build.timestamp.as_deref()?);
+        let latest = builds.first();
+        sample(
+            "hydra_check_job_success",
+            &labels,
+            u64::from(latest.is_some_and(|x| x.success)),
+        );
+        if let Some(time) = latest.and_then(timestamp) {
+            sample("hydra_check_job_build_timestamp_seconds", &labels, time);
+        }
+        let last_success = builds.iter().find(|x| x.success).and_then(timestamp);
+        if let Some(time) = last_success {
+            sample(
+                "hydra_check_job_last_success_timestamp_seconds",
+                &labels,
+                time,
+            );
+        }
+    }
+
+    /// Adds the Prometheus gauges of a `jobset` from its latest evaluation
+    /// among the `evals`, latest first.
+    pub(crate) fn push_jobset(&mut self, jobset: &str, evals: &[EvalStatus]) {
+        if self.format != OutputFormat::Prometheus {
+            return;
+        }
+        let Some(eval) = evals.first() else {
+            return;
+        };
+        let label = ("jobset", jobset.to_string());
+        let mut sample = |name, labels: Vec<(&'static str, String)>, value: Option<u64>| {
+            if let Some(value) = value {
+                self.samples.push(Sample {
+                    name,
+                    labels,
+                    value,
+                });
+            }
+        };
+        for (status, count) in [
+            ("succeeded", eval.succeeded),
+            ("failed", eval.failed),
+            ("queued", eval.queued),
+        ] {
+            let labels = vec![label.clone(), ("status", status.to_string())];
+            sample("hydra_check_jobset_builds", labels, count);
+        }
+        sample(
+            "hydra_check_jobset_latest_eval_id",
+            vec![label.clone()],
+            eval.id,
+        );
+        let name = "hydra_check_jobset_latest_eval_timestamp_seconds";
+        sample(name, vec![label.clone()], eval.timestamp);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok();
+        let age = eval
+            .timestamp
+            .zip(now)
+            .map(|(time, now)| now.as_secs().saturating_sub(time));
+        sample(
+            "hydra_check_jobset_latest_eval_age_seconds",
+            vec![label],
+            age,
+        );
+    }
+
+    /// Prints the remaining entries, or the whole `document` for JSON and
+    /// YAML.
+    pub(crate) fn finish(mut self, document: &impl Serialize) -> anyhow::Result<()> {
+        match self.format {
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(document)?),
+            OutputFormat::Yaml => print!("{}", format_yaml(&serde_json::to_value(document)?)),
+            OutputFormat::Csv => print!("{}", format_csv(&self.records)),
+            OutputFormat::Markdown => self.flush_group(),
+            OutputFormat::Junit => print!("{}", format_junit(&self.cases)),
+            OutputFormat::Prometheus => print!("{}", format_metrics(&self.samples)),
+            OutputFormat::Table | OutputFormat::Jsonl => {}
+        }
+        Ok(())
+    }
+
+    /// Prints the current group of Markdown records under its heading.
+    fn flush_group(&mut self) {
+        if self.records.is_empty() {
+            return;
+        }
+        if self.groups > 0 {
+            println!(); // vertical whitespace
+        }
+        if let Some(heading) = &self.heading {
+            println!("### {heading}\n");
+        }
+        print!("{}", format_markdown(&self.records));
+        self.records.clear();
+        self.groups += 1;
+    }
+}
+
+/// Flattens a JSON `value` into a [`Record`], with the keys of the nested
+/// objects and arrays joined by `.`, e.g. `cause.name` or `inputs.0.name`.
+/// Arrays of plain values are joined by spaces instead.
+fn flatten(value: &Value) -> Record {
+    fn flatten_into(prefix: &str, value: &Value, record: &mut Record) {
+        let key = |name: &str| match prefix {
+            "" => name.to_string(),
+            _ => format!("{prefix}.{name}"),
+        };
+        match value {
+            Value::Null => {}
+            Value::Array(items) if items.iter().all(|x| !x.is_array() && !x.is_object()) => {
+                let items: Vec<_> = items.iter().filter_map(plain_text).collect();
+                record.push((prefix.into(), items.join(" ")));
+            }
+            Value::Array(items) => {
+                for (idx, item) in items.iter().enumerate() {
+                    flatten_into(&key(&idx.to_string()), item, record);
+                }
+            }
+            Value::Object(fields) => {
+                for (name, item) in fields {
+                    flatten_into(&key(name), item, record);
+                }
+            }
+            x => record.extend(plain_text(x).map(|x| (prefix.to_string(), x))),
+        }
+    }
+    let mut record = Vec::new();
+    flatten_into("", value, &mut record);
+    record
+}
+
+/// Text of a plain JSON value, without the quotes of strings.
+fn plain_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(x) => Some(x.clone()),
+        x => Some(x.to_string()),
+    }
+}
+
+/// Lays out the `records` as rows under the union of their columns, in the
+/// order in which the columns first appear.
+fn layout(records: &[Record]) -> (Vec<&str>, Vec<Vec<&str>>) {
+    let mut columns: Vec<&str> = Vec::new();
+    for (name, _) in records.iter().flatten() {
+        if !columns.contains(&name.as_str()) {
+            columns.push(name);
+        }
+    }
+    let rows = records
+        .iter()
+        .map(|record| {
+            columns
+                .iter()
+                .map(|column| {
+                    record
+                        .iter()
+                        .find(|(name, _)| name == column)
+                        .map_or("", |(_, value)| value.as_str())
+                })
+                .collect()
+        })
+        .collect();
+    (columns, rows)
+}
+
+/// Formats the `records` as CSV with a header row, quoted as in RFC 4180.
+fn format_csv(records: &[Record]) -> String {
+    let quote = |field: &str| -> String {
+        match field.contains([',', '"', '\n', '\r']) {
+            true => format!("\"{}\"", field.replace('"', "\"\"")),
+            false => field.into(),
+        }
+    };
+    let (columns, rows) = layout(records);
+    let mut csv = String::new();
+    for row in std::iter::once(columns).chain(rows) {
+        let row: Vec<_> = row.into_iter().map(quote).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Formats the `records` as a Markdown table (in the GitHub flavor).
+fn format_markdown(records: &[Record]) -> String {
+    let escape = |cell: &str| cell.replace('|', "\\|").replace('\n', "<br>");
+    let (columns, rows) = layout(records);
+    let separator = vec!["---"; columns.len()];
+    let mut markdown = String::new();
+    for row in [columns, separator].into_iter().chain(rows) {
+        let row: Vec<_> = row.into_iter().map(escape).collect();
+        markdown.push_str("| ");
+        markdown.push_str(&row.join(" | "));
+        markdown.push_str(" |\n");
+    }
+    markdown
+}
+
+/// Formats the test `cases` as a JUnit XML report, with a test suite for
+/// each jobset.
+fn format_junit(cases: &[TestCase]) -> String {
+    let counts = |cases: &[&TestCase]| {
+        let count = |element| {
+            let outcomes = cases.iter().filter_map(|x| x.outcome.as_ref());
+            outcomes.filter(|x| x.element == element).count()
+        };
+        format!(
+            r#"tests="{}" failures="{}" errors="{}" skipped="{}""#,
+            cases.len(),
+            count("failure"),
+            count("error"),
+            count("skipped")
+        )
+    };
+    let mut suites: IndexMap<&str, Vec<&TestCase>> = IndexMap::new();
+    for case in cases {
+        suites.entry(&case.suite).or_default().push(case);
+    }
+    let all: Vec<_> = cases.iter().collect();
+    let mut lines = vec![
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#.to_string(),
+        format!(r#"<testsuites name="hydra-check" {}>"#, counts(&all)),
+    ];
+    for (suite, cases) in suites {
+        let suite = escape_xml(suite);
+        lines.push(format!(
+            r#"  <testsuite name="{suite}" {}>"#,
+            counts(&cases)
+        ));
+        for case in cases {
+            let testcase = format!(
+                r#"    <testcase name="{}" classname="{suite}""#,
+                escape_xml(&case.name)
+            );
+            let Some(outcome) = &case.outcome else {
+                lines.push(format!("{testcase}/>"));
+                continue;
+            };
+            let kind = match outcome.element {
+                "skipped" => String::new(),
+                _ => format!(r#" type="{}""#, escape_xml(&outcome.kind)),
+            };
+            let element = format!(
+                r#"<{}{kind} message="{}""#,
+                outcome.element,
+                escape_xml(&outcome.message)
+            );
+            let element = match outcome.details.as_str() {
+                "" => format!("{element}/>"),
+                details => format!("{element}>{}</{}>", escape_xml(details), outcome.element),
+            };
+            lines.push(format!("{testcase}>"));
+            lines.push(format!("      {element}"));
+            lines.push("    </testcase>".into());
+        }
+        lines.push("  </testsuite>".into());
+    }
+    lines.push("</testsuites>\n".into());
+    lines.join("\n")
+}
+
+/// Escapes the special characters of XML, for both text and attributes,
+/// and drops the control characters (e.g. of colored logs) that are not
+/// allowed in XML 1.0.
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for x in text.chars() {
+        match x {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(x),
+            x if x.is_control() => {}
+            x => escaped.push(x),
+        }
+    }
+    escaped
+}
+
+/// Formats the `samples` in the OpenMetrics text format, grouped into the
+/// [`METRICS`] with their help texts.
+fn format_metrics(samples: &[Sample]) -> String {
+    let escape = |value: &str| {
+        value
+            .replace('\\', r"\\")
+            .replace('"', r#"\""#)
+            .replace('\n', r"\n")
+    };
+    let mut lines = Vec::new();
+    for (name, help) in METRICS {
+        let mut samples = samples.iter().filter(|x| x.name == *name).peekable();
+        if samples.peek().is_none() {
+            continue;
+        }
+        lines.push(format!("# HELP {name} {help}"));
+        lines.push(format!("# TYPE {name} gauge"));
+        for sample in samples {
+            let labels: Vec<_> = sample
+                .labels
+                .iter()
+                .map(|(key, value)| format!(r#"{key}="{}""#, escape(value)))
+                .collect();
+            lines.push(format!("{name}{{{}}} {}", labels.join(","), sample.value));
+        }
+    }
+    lines.push("# EOF\n".into());
+    lines.join("\n")
+}
+
+/// Formats a JSON `value` as a YAML document in the block style.
+fn format_yaml(value: &Value) -> String {
+    fn yaml_lines(value: &Value) -> Vec<String> {
+        let nested = |item: &Value| match item {
+            Value::Array(x) => !x.is_empty(),
+            Value::Object(x) => !x.is_empty(),
+            _ => false,
+        };
+        match value {
+            Value::Array(items) if !items.is_empty() => items
+                .iter()
+                .flat_map(|item| {
+                    let mut lines = yaml_lines(item).into_iter();
+                    let first = lines.next().unwrap_or_default();
+                    std::iter::once(format!("- {first}"))
+                        .chain(lines.map(|line| format!("  {line}")))
+                })
+                .collect(),
+            Value::Object(fields) if !fields.is_empty() => fields
+                .iter()
+                .flat_map(|(name, item)| {
+                    let name = yaml_scalar(name);
+                    let lines = yaml_lines(item);
+                    match nested(item) {
+                        true => std::iter::once(format!("{name}:"))
+                            .chain(lines.into_iter().map(|line| format!("  {line}")))
+                            .collect::<Vec<_>>(),
+                        false => vec![format!("{name}: {}", lines.concat())],
+                    }
+                })
+                .collect(),
+            Value::Array(_) => vec!["[]".into()],
+            Value::Object(_) => vec!["{}".into()],
+            Value::String(x) => vec![yaml_scalar(x)],
+            x => vec![x.to_string()],
+        }
+    }
+    let mut yaml = String::new();
+    for line in yaml_lines(value) {
+        yaml.push_str(&line);
+        yaml.push('\n');
+    }
+    yaml
+}
+
+/// Formats a string as a plain YAML scalar if that is unambiguous, and as
+/// a double-quoted one otherwise, which is compatible with JSON strings.
+fn yaml_scalar(text: &str) -> String {
+    let reserved = [
+        "true", "false", "yes", "no", "on", "off", "null", "y", "n", "~",
+    ];
+    let plain = text.starts_with(|x: char| x.is_ascii_alphabetic() || x == '/' || x == '_')
+        && !text.ends_with(' ')
+        && !reserved.contains(&text.to_lowercase().as_str())
+        && text
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || " _./+-()".contains(x));
+    match plain {
+        true => text.into(),
+        false => Value::from(text).to_string(),
+    }
+}
+
+#[test]
+fn render_records() {
+    let builds = serde_json::json!([
+        {
+            "package": "hello.x86_64-linux",
+            "icon": "✖",
+            "success": false,
+            "status": "Dependency failed",
+            "build_url": "https://hydra.nixos.org/build/289870122",
+            "cause": { "name": "perl-5.40.0", "status": "Failed" },
+        },
+        {
+            "package": "hello.x86_64-linux",
+            "icon": "⧖",
+            "success": false,
+            "status": "Queued: no build, \"still\" | queued",
+            "short_revs": ["0196c01", "8c4dc69"],
+            "inputs": [{ "name": "nixpkgs" }, {}],
+        },
+    ]);
+    let records: Vec<_> = builds.as_array().unwrap().iter().map(flatten).collect();
+    assert_snapshot!(format_csv(&records), @r#"
+    package,icon,success,status,build_url,cause.name,cause.status,short_revs,inputs.0.name
+    hello.x86_64-linux,✖,false,Dependency failed,https://hydra.nixos.org/build/289870122,perl-5.40.0,Failed,,
+    hello.x86_64-linux,⧖,false,"Queued: no build, ""still"" | queued",,,,0196c01 8c4dc69,nixpkgs
+    "#);
+    assert_snapshot!(format_markdown(&records), @r#"
+    | package | icon | success | status | build_url | cause.name | cause.status | short_revs | inputs.0.name |
+    | --- | --- | --- | --- | --- | --- | --- | --- | --- |
+    | hello.x86_64-linux | ✖ | false | Dependency failed | https://hydra.nixos.org/build/289870122 | perl-5.40.0 | Failed |  |  |
+    | hello.x86_64-linux | ⧖ | false | Queued: no build, "still" \| queued |  |  |  | 0196c01 8c4dc69 | nixpkgs |
+    "#);
+    let document = serde_json::json!({ "hello.x86_64-linux": builds, "1809808": {}, "null": [] });
+    assert_snapshot!(format_yaml(&document), @r#"
+    hello.x86_64-linux:
+      - package: hello.x86_64-linux
+        icon: "✖"
+        success: false
+        status: Dependency failed
+        build_url: "https://hydra.nixos.org/build/289870122"
+        cause:
+          name: perl-5.40.0
+          status: Failed
+      - package: hello.x86_64-linux
+        icon: "⧖"
+        success: false
+        status: "Queued: no build, \"still\" | queued"
+        short_revs:
+          - "0196c01"
+          - "8c4dc69"
+        inputs:
+          - name: nixpkgs
+          - {}
+    "1809808": {}
+    "null": []
+    "#);
+}
+
+#[test]
+fn render_junit() {
+    let failed = BuildStatus {
+        icon: StatusIcon::Failed,
+        status: "Dependency failed".into(),
+        build_url: Some("https://hydra.nixos.org/build/289870122".into()),
+        evals: true,
+        cause: Some(crate::FailureCause {
+            status: "Failed".into(),
+            name: Some("perl-5.40.0".into()),
+            build_id: None,
+            build_url: Some("https://hydra.nixos.org/build/289869999".into()),
+            log_url: None,
+        }),
+        log_tail: Some("\u{1b}[31merror:\u{1b}[0m builder for '<perl>' failed".into()),
+        ..Default::default()
+    };
+    let succeeded = BuildStatus {
+        icon: StatusIcon::Succeeded,
+        success: true,
+        ..Default::default()
+    };
+    let queued = BuildStatus {
+        icon: StatusIcon::Queued,
+        status: "Queued: no build has been attempted for this package yet".into(),
+        ..Default::default()
+    };
+    let missing = HydraError::NotFound {
+        url: "https://hydra.nixos.org/job/nixpkgs/unstable/nonexistent".into(),
+        message: "the page does not exist".into(),
+    };
+    let mut renderer = Renderer::new(OutputFormat::Junit);
+    let unstable = "nixpkgs/unstable";
+    renderer.push_job(unstable, "hello.x86_64-linux", Ok(&[failed]));
+    renderer.push_job(unstable, "hello.aarch64-linux", Ok(&[succeeded]));
+    renderer.push_job(
+        "nixos/unstable",
+        "nixpkgs.hello.x86_64-linux",
+        Ok(&[queued]),
+    );
+    renderer.push_job(unstable, "nonexistent.x86_64-linux", Err(&missing));
+    renderer.push_job(unstable, "empty.x86_64-linux", Ok(&[]));
+    assert_snapshot!(format_junit(&renderer.cases), @r#"
+        <?xml version="1.0" encoding="UTF-8"?>
+        <testsuites name="hydra-check" tests="5" failures="1" errors="1" skipped="2">
+          <testsuite name="nixpkgs/unstable" tests="4" failures="1" errors="1" skipped="1">
+            <testcase name="hello.x86_64-linux" classname="nixpkgs/unstable">
+              <failure type="Dependency failed" message="Dependency failed">build: https://hydra.nixos.org/build/289870122
+        cause: perl-5.40.0 (Failed) https://hydra.nixos.org/build/289869999
+        log:
+        error: builder for &apos;&lt;perl&gt;&apos; failed</failure>
+            </testcase>
+            <testcase name="hello.aarch64-linux" classname="nixpkgs/unstable"/>
+            <testcase name="nonexistent.x86_64-linux" classname="nixpkgs/unstable">
+              <error type="not-found" message="the page does not exist (https://hydra.nixos.org/job/nixpkgs/unstable/nonexistent)"/>
+            </testcase>
+            <testcase name="empty.x86_64-linux" classname="nixpkgs/unstable">
+              <skipped message="no builds of the job"/>
+            </testcase>
+          </testsuite>
+          <testsuite name="nixos/unstable" tests="1" failures="0" errors="0" skipped="1">
+            <testcase name="nixpkgs.hello.x86_64-linux" classname="nixos/unstable">
+              <skipped message="Queued: no build has been attempted for this package yet"/>
+            </testcase>
+          </testsuite>
+        </testsuites>
+    "#);
+}
+
+#[test]
+fn render_metrics() {
+    let build = |success, timestamp: &str| BuildStatus {
+        success,
+        timestamp: Some(timestamp.into()),
+        ..Default::default()
+    };
+    let builds = [
+        build(false, "2025-02-22T07:47:19Z"),
+        build(false, "2025-02-20T10:02:37Z"),
+        build(true, "2025-02-18T13:45:02Z"),
+    ];
+    let missing = HydraError::NotFound {
+        url: "https://hydra.nixos.org/job/nixpkgs/unstable/nonexistent".into(),
+        message: "the page does not exist".into(),
+    };
+    let eval = EvalStatus {
+        id: Some(1_809_808),
+        timestamp: Some(1_740_211_639),
+        succeeded: Some(201_785),
+        failed: Some(5_273),
+        queued: Some(0),
+        ..Default::default()
+    };
+    let mut renderer = Renderer::new(OutputFormat::Prometheus);
+    let unstable = "nixpkgs/unstable";
+    renderer.push_job(unstable, "hello.x86_64-linux", Ok(&builds));
+    renderer.push_job(unstable, "hello.aarch64-linux", Ok(&builds[2..]));
+    renderer.push_job(unstable, "nonexistent.x86_64-linux", Err(&missing));
+    renderer.push_job(unstable, "weird\"name\\", Ok(&[]));
+    renderer.push_jobset(unstable, &[eval]);
+    let metrics = format_metrics(&renderer.samples);
+    let (age, metrics): (Vec<_>, Vec<_>) = metrics
+        .lines()
+        .partition(|x| x.starts_with("hydra_check_jobset_latest_eval_age_seconds{"));
+    assert!(age[0].ends_with(char::is_numeric));
+    assert_snapshot!(metrics.join("\n"), @r#"
+        # HELP hydra_check_job_success Whether the latest build of the job has succeeded
+        # TYPE hydra_check_job_success gauge
+        hydra_check_job_success{jobset="nixpkgs/unstable",job="hello.x86_64-linux"} 0
+        hydra_check_job_success{jobset="nixpkgs/unstable",job="hello.aarch64-linux"} 1
+        hydra_check_job_success{jobset="nixpkgs/unstable",job="weird\"name\\"} 0
+        # HELP hydra_check_job_build_timestamp_seconds Time of the latest build of the job
+        # TYPE hydra_check_job_build_timestamp_seconds gauge
+        hydra_check_job_build_timestamp_seconds{jobset="nixpkgs/unstable",job="hello.x86_64-linux"} 1740210439
+        hydra_check_job_build_timestamp_seconds{jobset="nixpkgs/unstable",job="hello.aarch64-linux"} 1739886302
+        # HELP hydra_check_job_last_success_timestamp_seconds Time of the latest successful build of the job, among the fetched builds
+        # TYPE hydra_check_job_last_success_timestamp_seconds gauge
+        hydra_check_job_last_success_timestamp_seconds{jobset="nixpkgs/unstable",job="hello.x86_64-linux"} 1739886302
+        hydra_check_job_last_success_timestamp_seconds{jobset="nixpkgs/unstable",job="hello.aarch64-linux"} 1739886302
+        # HELP hydra_check_job_error Whether the job could not be checked, e.g. as it is not evaluated
+        # TYPE hydra_check_job_error gauge
+        hydra_check_job_error{jobset="nixpkgs/unstable",job="nonexistent.x86_64-linux",kind="not-found"} 1
+        # HELP hydra_check_jobset_builds Number of builds of the latest evaluation of the jobset, by status
+        # TYPE hydra_check_jobset_builds gauge
+        hydra_check_jobset_builds{jobset="nixpkgs/unstable",status="succeeded"} 201785
+        hydra_check_jobset_builds{jobset="nixpkgs/unstable",status="failed"} 5273
+        hydra_check_jobset_builds{jobset="nixpkgs/unstable",status="queued"} 0
+        # HELP hydra_check_jobset_latest_eval_id ID of the latest evaluation of the jobset
+        # TYPE hydra_check_jobset_latest_eval_id gauge
+        hydra_check_jobset_latest_eval_id{jobset="nixpkgs/unstable"} 1809808
+        # HELP hydra_check_jobset_latest_eval_timestamp_seconds Time of the latest evaluation of the jobset
+        # TYPE hydra_check_jobset_latest_eval_timestamp_seconds gauge
+        hydra_check_jobset_latest_eval_timestamp_seconds{jobset="nixpkgs/unstable"} 1740211639
+        # HELP hydra_check_jobset_latest_eval_age_seconds Seconds since the latest evaluation of the jobset
+        # TYPE hydra_check_jobset_latest_eval_age_seconds gauge
+        # EOF
+    "#);
+}
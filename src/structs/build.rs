@@ -2,24 +2,67 @@ use anyhow::bail;
 use colored::{ColoredString, Colorize};
 use scraper::ElementRef;
 use serde::Serialize;
+use serde_json::Value;
 use serde_with::skip_serializing_none;
 
-use crate::{is_skipable_row, ShowHydraStatus, SoupFind, StatusIcon, TryAttr};
+#[cfg(test)]
+use insta::assert_snapshot;
 
+use crate::{
+    backends::iso8601_from_unix, is_skipable_row, ShowHydraStatus, SoupFind, StatusIcon, TryAttr,
+};
+
+#[non_exhaustive]
 #[skip_serializing_none]
 #[derive(Serialize, Debug, Default, Clone)]
 /// Status of a single build attempt, can be serialized to a JSON entry
-pub(crate) struct BuildStatus {
-    pub(crate) icon: StatusIcon,
-    pub(crate) success: bool,
-    pub(crate) status: String,
-    pub(crate) timestamp: Option<String>,
-    pub(crate) build_id: Option<String>,
-    pub(crate) build_url: Option<String>,
-    pub(crate) name: Option<String>,
-    pub(crate) arch: Option<String>,
-    pub(crate) evals: bool,
-    pub(crate) job_name: Option<String>,
+pub struct BuildStatus {
+    /// Overall status of the build
+    pub icon: StatusIcon,
+    /// Whether the build succeeded
+    pub success: bool,
+    /// Status text from Hydra, e.g. `Dependency failed`,
+    /// or the error message if there is no build
+    pub status: String,
+    /// Date and time of the build in ISO 8601
+    pub timestamp: Option<String>,
+    /// Build ID
+    pub build_id: Option<String>,
+    /// URL of the build on Hydra
+    pub build_url: Option<String>,
+    /// Name of the derivation, e.g. `hello-2.12.1`
+    pub name: Option<String>,
+    /// System of the build, e.g. `x86_64-linux`
+    pub arch: Option<String>,
+    /// Whether the build is part of an evaluation, i.e. an actual build
+    /// instead of a placeholder for an error or a queued job
+    pub evals: bool,
+    /// Name of the job, only available in some cases (e.g. in eval details)
+    pub job_name: Option<String>,
+    /// The step that actually failed, only resolved for the latest failed
+    /// build with `Dependency failed`
+    pub cause: Option<FailureCause>,
+    /// Last lines of the build log, or of the log of the failed dependency,
+    /// only fetched for the latest failed build with `--log`
+    pub log_tail: Option<String>,
+}
+
+#[non_exhaustive]
+#[skip_serializing_none]
+#[derive(Serialize, Debug, Default, Clone)]
+/// The build step that actually failed, for a build that fails because of
+/// one of its (possibly transitive) dependencies
+pub struct FailureCause {
+    /// Status text of the failed step, e.g. `Failed` or `Timed out`
+    pub status: String,
+    /// Name of the failed derivation, e.g. `openssl-3.3.2`
+    pub name: Option<String>,
+    /// ID of the build that the failed step belongs to
+    pub build_id: Option<String>,
+    /// URL of the build that the failed step belongs to
+    pub build_url: Option<String>,
+    /// URL of the log of the failed step, if available
+    pub log_url: Option<String>,
 }
 
 impl ShowHydraStatus for BuildStatus {
@@ -53,10 +96,21 @@ impl ShowHydraStatus for BuildStatus {
         row.extend_from_slice(details);
         let build_url = self.build_url.clone().unwrap_or_default().dimmed();
         row.push(build_url);
+        if let Some(cause) = &self.cause {
+            let name = cause.name.as_deref().unwrap_or("unknown derivation");
+            let build_url = cause.build_url.as_deref().unwrap_or_default();
+            row.push(format!("⤷ {} ({})", name.red(), cause.status).into());
+            row.push(build_url.dimmed());
+        }
         row
     }
 }
 
+/// Status of a build that has not been attempted yet, as shown for the
+/// `Queued` label on the web page of Hydra.
+const QUEUED_STATUS: &str =
+    "Queued: no build has been attempted for this package yet (still queued)";
+
 impl BuildStatus {
     pub(crate) fn from_tbody(tbody: ElementRef<'_>) -> anyhow::Result<Vec<Self>> {
         let mut builds = Vec::new();
@@ -99,8 +153,7 @@ impl BuildStatus {
             if let Ok(span_status) = status.find("span") {
                 let span_status: String = span_status.text().collect();
                 let status = if span_status.trim() == "Queued" {
-                    "Queued: no build has been attempted for this package yet (still queued)"
-                        .to_string()
+                    QUEUED_STATUS.to_string()
                 } else {
                     format!("Unknown Hydra status: {span_status}")
                 };
@@ -118,13 +171,7 @@ impl BuildStatus {
             let name = name.text().collect();
             let job_name = job_name.map(|x| x.text().collect::<String>().trim().into());
             let arch = arch.find("tt")?.text().collect();
-            let success = status == "Succeeded";
-            let icon = match (success, status) {
-                (true, _) => StatusIcon::Succeeded,
-                (false, "Cancelled") => StatusIcon::Cancelled,
-                (false, "Queued") => StatusIcon::Queued,
-                (false, _) => StatusIcon::Failed,
-            };
+            let (success, icon) = Self::icon_from_status(status);
             let evals = true;
             builds.push(BuildStatus {
                 icon,
@@ -137,8 +184,137 @@ impl BuildStatus {
                 arch: Some(arch),
                 evals,
                 job_name,
+                cause: None,
+                log_tail: None,
             });
         }
         Ok(builds)
     }
+
+    /// Reads a single build from the JSON API of Hydra, e.g. from
+    /// <https://hydra.nixos.org/build/290062156> with `Accept: application/json`.
+    pub(crate) fn from_json(build: &Value, host_url: &str) -> anyhow::Result<Self> {
+        let Some(id) = build["id"].as_u64() else {
+            bail!("error while parsing build status from: {build}");
+        };
+        let finished = match &build["finished"] {
+            Value::Bool(x) => *x,
+            x => x.as_u64().is_some_and(|x| x != 0),
+        };
+        if !finished {
+            // same as a queued build on the web page, see `from_tbody`
+            return Ok(BuildStatus {
+                icon: StatusIcon::Queued,
+                status: QUEUED_STATUS.into(),
+                ..Default::default()
+            });
+        }
+        // see `buildstatus` in hydra's src/root/common.tt
+        let status = match build["buildstatus"].as_u64() {
+            Some(0) => "Succeeded",
+            Some(1) => "Failed",
+            Some(2) => "Dependency failed",
+            Some(3) => "Aborted",
+            Some(4) => "Cancelled",
+            Some(6) => "Failed with output",
+            Some(7) => "Timed out",
+            Some(9) => "Unsupported system type",
+            Some(10) => "Log limit exceeded",
+            Some(11) => "Output limit exceeded",
+            Some(12) => "Non-deterministic build",
+            _ => "Unknown",
+        };
+        let (success, icon) = Self::icon_from_status(status);
+        let timestamp = match build["stoptime"].as_u64() {
+            Some(x) if x != 0 => Some(x),
+            _ => build["timestamp"].as_u64(),
+        };
+        let to_string = |key: &str| build[key].as_str().map(str::to_string);
+        Ok(BuildStatus {
+            icon,
+            success,
+            status: status.into(),
+            timestamp: timestamp.map(iso8601_from_unix),
+            build_id: Some(id.to_string()),
+            build_url: Some(format!("{host_url}/build/{id}")),
+            name: to_string("nixname"),
+            arch: to_string("system"),
+            evals: true,
+            job_name: None,
+            cause: None,
+            log_tail: None,
+        })
+    }
+
+    pub(crate) fn icon_from_status(status: &str) -> (bool, StatusIcon) {
+        let success = status == "Succeeded";
+        let icon = match (success, status) {
+            (true, _) => StatusIcon::Succeeded,
+            (false, "Cancelled") => StatusIcon::Cancelled,
+            (false, "Queued") => StatusIcon::Queued,
+            (false, _) => StatusIcon::Failed,
+        };
+        (success, icon)
+    }
+}
+
+#[test]
+fn build_status_from_json() {
+    let build = serde_json::json!({
+        "id": 290_062_156,
+        "finished": 1,
+        "buildstatus": 2,
+        "nixname": "hello-2.12.1",
+        "system": "x86_64-linux",
+        "timestamp": 1_728_518_400,
+        "stoptime": 1_728_561_599,
+        "jobsetevals": [1_809_808],
+    });
+    let build = BuildStatus::from_json(&build, "https://hydra.nixos.org").unwrap();
+    assert!(!build.success);
+    assert_eq!(build.status, "Dependency failed");
+    assert_eq!(build.timestamp.as_deref(), Some("2024-10-10T11:59:59Z"));
+    assert_eq!(build.name.as_deref(), Some("hello-2.12.1"));
+    assert!(build
+        .build_url
+        .is_some_and(|x| x.ends_with("/build/290062156")));
+
+    let build = serde_json::json!({"id": 1, "finished": 1, "buildstatus": 9});
+    let build = BuildStatus::from_json(&build, "https://hydra.nixos.org").unwrap();
+    assert_eq!(build.status, "Unsupported system type");
+    assert_eq!(build.icon, StatusIcon::Failed);
+
+    // same as a queued build from the web page, regardless of the backend
+    let build = serde_json::json!({"id": 2, "finished": 0, "buildstatus": null});
+    let build = BuildStatus::from_json(&build, "https://hydra.nixos.org").unwrap();
+    assert_eq!(build.icon, StatusIcon::Queued);
+    assert_eq!(build.status, QUEUED_STATUS);
+    assert!(!build.evals && !build.success);
+}
+
+#[test]
+fn build_status_codes() {
+    let statuses: Vec<_> = (0..=13)
+        .map(|code| {
+            let build = serde_json::json!({"id": 1, "finished": 1, "buildstatus": code});
+            let build = BuildStatus::from_json(&build, "https://hydra.nixos.org").unwrap();
+            format!("{code:>2} {} {}", build.icon, build.status)
+        })
+        .collect();
+    assert_snapshot!(statuses.join("\n"), @r#"
+         0 ✔ Succeeded
+         1 ✖ Failed
+         2 ✖ Dependency failed
+         3 ✖ Aborted
+         4 ⏹ Cancelled
+         5 ✖ Unknown
+         6 ✖ Failed with output
+         7 ✖ Timed out
+         8 ✖ Unknown
+         9 ✖ Unsupported system type
+        10 ✖ Log limit exceeded
+        11 ✖ Output limit exceeded
+        12 ✖ Non-deterministic build
+        13 ✖ Unknown
+    "#);
 }
@@ -6,36 +6,59 @@ use serde_with::skip_serializing_none;
 use crate::{constants, ShowHydraStatus, StatusIcon};
 
 /// Specification for a single Hydra evaluation, with an optional filter.
-/// Should only be constructed with [`Evaluation::guess_from_spec`]
-/// to ensure a correct [`Evaluation::spec`], thus marked [`non_exhaustive`].
+/// Should only be constructed with `Evaluation::guess_from_spec`
+/// to ensure a correct [`Evaluation::spec`], thus marked `non_exhaustive`.
 #[non_exhaustive]
 #[derive(Debug, Clone, Serialize)]
-pub(crate) struct Evaluation {
+pub struct Evaluation {
+    /// Normalized specification, i.e. `<id>/<filter>`
     #[serde(skip)]
-    pub(crate) spec: String,
-    pub(crate) id: u64,
-    pub(crate) filter: Option<String>,
-    pub(crate) long: bool,
+    pub spec: String,
+    /// Evaluation ID, where `0` stands for the latest evaluation of a jobset
+    pub id: u64,
+    /// Filter for the jobs of the evaluation
+    pub filter: Option<String>,
+    /// Whether to fetch all jobs instead of only the first page
+    pub long: bool,
+    /// Another evaluation to compare with, possibly of another jobset,
+    /// instead of the previous evaluation of the jobset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compare: Option<u64>,
 }
 
+#[non_exhaustive]
 #[skip_serializing_none]
 #[derive(Serialize, Debug, Default, Clone)]
 /// Status of a single evaluation, can be serialized to a JSON entry
-pub(crate) struct EvalStatus {
-    pub(crate) icon: StatusIcon,
-    pub(crate) finished: Option<bool>,
-    pub(crate) id: Option<u64>,
-    pub(crate) url: Option<String>,
-    pub(crate) datetime: Option<String>,
-    pub(crate) relative: Option<String>,
-    pub(crate) timestamp: Option<u64>,
-    pub(crate) status: String,
-    pub(crate) short_rev: Option<String>,
-    pub(crate) input_changes: Option<String>,
-    pub(crate) succeeded: Option<u64>,
-    pub(crate) failed: Option<u64>,
-    pub(crate) queued: Option<u64>,
-    pub(crate) delta: Option<String>,
+pub struct EvalStatus {
+    /// Overall status, [`StatusIcon::Queued`] if there are still queued builds
+    pub icon: StatusIcon,
+    /// Whether all the builds of the evaluation have finished
+    pub finished: Option<bool>,
+    /// Evaluation ID
+    pub id: Option<u64>,
+    /// URL of the evaluation on Hydra
+    pub url: Option<String>,
+    /// Date and time of the evaluation in ISO 8601
+    pub datetime: Option<String>,
+    /// Relative time of the evaluation, e.g. `5h ago`
+    pub relative: Option<String>,
+    /// Unix timestamp of the evaluation
+    pub timestamp: Option<u64>,
+    /// Status text from Hydra, or the error message if there is no evaluation
+    pub status: String,
+    /// Short git revision of the main input, e.g. of nixpkgs
+    pub short_rev: Option<String>,
+    /// Summary of the changed inputs, e.g. `nixpkgs → df76cd6`
+    pub input_changes: Option<String>,
+    /// Number of succeeded builds
+    pub succeeded: Option<u64>,
+    /// Number of failed builds
+    pub failed: Option<u64>,
+    /// Number of queued builds
+    pub queued: Option<u64>,
+    /// Change in the number of succeeded builds, relative to the previous evaluation
+    pub delta: Option<String>,
 }
 
 impl ShowHydraStatus for EvalStatus {
@@ -127,17 +150,31 @@ impl Evaluation {
             Some(x) if x.trim().is_empty() => None,
             Some(x) => Some(x.into()),
         };
+        Self::new(id, filter.as_deref(), long)
+    }
+
+    /// Constructs an evaluation with a normalized [`Evaluation::spec`].
+    pub(crate) fn new(id: u64, filter: Option<&str>, long: bool) -> Self {
         Self {
             spec: format!(
                 "{id}{}",
-                match &filter {
+                match filter {
                     Some(x) => format!("/{x}"),
                     None => "".into(),
                 }
             ),
             id,
-            filter,
+            filter: filter.map(str::to_string),
             long,
+            compare: None,
+        }
+    }
+
+    /// Compares the evaluation with another evaluation `base`.
+    pub(crate) fn compared_to(self, base: u64) -> Self {
+        Self {
+            compare: Some(base),
+            ..self
         }
     }
 }
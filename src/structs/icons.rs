@@ -1,12 +1,18 @@
 use colored::{ColoredString, Colorize};
 use serde_with::SerializeDisplay;
 
-#[derive(SerializeDisplay, Debug, Clone, Default)]
-pub(crate) enum StatusIcon {
+/// Status of a build or an evaluation, serialized as an icon
+#[derive(SerializeDisplay, Debug, Clone, Default, PartialEq, Eq)]
+pub enum StatusIcon {
+    /// ✔
     Succeeded,
+    /// ✖
     Failed,
+    /// ⏹
     Cancelled,
+    /// ⧖
     Queued,
+    /// ⚠, also used for errors reported by Hydra
     #[default]
     Warning,
 }
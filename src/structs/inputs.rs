@@ -11,16 +11,22 @@ use insta::assert_snapshot;
 
 use crate::{is_skipable_row, SoupFind};
 
+#[non_exhaustive]
 #[skip_serializing_none]
 #[derive(Serialize, Clone, Default, Debug)]
 /// Inputs of a given evaluation (which is also the inputs of a package build)
-pub(crate) struct EvalInput {
-    pub(crate) name: Option<String>,
+pub struct EvalInput {
+    /// Name of the input, e.g. `nixpkgs`
+    pub name: Option<String>,
+    /// Type of the input, e.g. `Git checkout`
     #[serde(rename = "type")]
-    pub(crate) input_type: Option<String>,
-    pub(crate) value: Option<String>,
-    pub(crate) revision: Option<String>,
-    pub(crate) store_path: Option<String>,
+    pub input_type: Option<String>,
+    /// Value of the input, e.g. the URL of a git repository
+    pub value: Option<String>,
+    /// Revision of the input, e.g. a git commit hash
+    pub revision: Option<String>,
+    /// Store path of the input
+    pub store_path: Option<String>,
 }
 
 impl Display for EvalInput {
@@ -83,6 +89,27 @@ impl EvalInput {
         }
         Ok(inputs)
     }
+
+    /// Reads the `jobsetevalinputs` of an evaluation from the JSON API of
+    /// Hydra, e.g. from <https://hydra.nixos.org/eval/1809808>.
+    pub(crate) fn from_json(eval: &Value) -> Vec<Self> {
+        let Some(inputs) = eval["jobsetevalinputs"].as_object() else {
+            return vec![];
+        };
+        inputs
+            .iter()
+            .map(|(name, input)| {
+                let to_string = |key: &str| input[key].as_str().map(str::to_string);
+                EvalInput {
+                    name: Some(name.into()),
+                    input_type: to_string("type"),
+                    value: to_string("uri").or_else(|| to_string("value")),
+                    revision: to_string("revision"),
+                    store_path: None,
+                }
+            })
+            .collect()
+    }
 }
 
 #[test]
@@ -4,8 +4,8 @@ mod icons;
 mod inputs;
 mod release;
 
-pub(crate) use build::BuildStatus;
-pub(crate) use eval::{EvalStatus, Evaluation};
-pub(crate) use icons::StatusIcon;
-pub(crate) use inputs::EvalInput;
-pub(crate) use release::ReleaseStatus;
+pub use build::{BuildStatus, FailureCause};
+pub use eval::{EvalStatus, Evaluation};
+pub use icons::StatusIcon;
+pub use inputs::EvalInput;
+pub use release::ReleaseStatus;
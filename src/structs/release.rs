@@ -1,24 +1,30 @@
-use std::fmt::Display;
+use std::{collections::VecDeque, fmt::Display};
 
 use colored::{ColoredString, Colorize};
 use serde::Serialize;
 use serde_with::skip_serializing_none;
 use yansi::hyperlink::HyperlinkExt;
 
-use crate::{constants, BuildStatus, EvalStatus, ShowHydraStatus, StatusIcon};
+use crate::{BuildStatus, EvalStatus, HydraClient, ShowHydraStatus, StatusIcon};
 
 /// Container for the evaluation and test build status of a (potential)
 /// channel release.
 #[non_exhaustive]
 #[skip_serializing_none]
 #[derive(Serialize, Debug, Default, Clone)]
-pub(crate) struct ReleaseStatus {
-    pub(crate) eval: EvalStatus,
-    pub(crate) test: BuildStatus,
-    pub(crate) release_url: Option<String>,
-    pub(crate) report_url: Option<String>,
-    pub(crate) git_revision: Option<String>,
-    pub(crate) git_revision_url: Option<String>,
+pub struct ReleaseStatus {
+    /// Status of the channel evaluation
+    pub eval: EvalStatus,
+    /// Status of the release test, e.g. the aggregate job `tested`
+    pub test: BuildStatus,
+    /// URL of the release, e.g. on <https://releases.nixos.org>
+    pub release_url: Option<String>,
+    /// URL of the evaluation report of nix-review-tools
+    pub report_url: Option<String>,
+    /// Git revision of nixpkgs for the release
+    pub git_revision: Option<String>,
+    /// URL of the git revision on GitHub
+    pub git_revision_url: Option<String>,
 }
 
 impl ShowHydraStatus for ReleaseStatus {
@@ -113,6 +119,20 @@ impl ShowHydraStatus for ReleaseStatus {
     }
 }
 
+/// Expands a template for the URL of a release, see
+/// [`HydraClient::with_release_url`].
+fn expand_release_url(template: &str, channel: &str, name: &str) -> String {
+    let channel_path = if channel == "nixpkgs-unstable" {
+        "nixpkgs".into()
+    } else {
+        channel.replacen('-', "/", 1)
+    };
+    template
+        .replace("{channel_path}", &channel_path)
+        .replace("{channel}", channel)
+        .replace("{name}", name)
+}
+
 impl ReleaseStatus {
     pub(crate) fn new(
         eval: EvalStatus,
@@ -120,41 +140,39 @@ impl ReleaseStatus {
         channel: &str,
         jobset: &str,
         always_link: bool,
+        client: &HydraClient,
     ) -> Self {
-        let (release_url, git_revision, git_revision_url) = if constants::is_default_host_url()
-            && (
-                channel.starts_with("nixpkgs-") || channel.starts_with("nixos-")
-                // see: https://channels.nixos.org
-            ) {
-            if let Some(test_name) = test.name.as_deref() {
-                let release_url =
-                    if always_link || (eval.finished.unwrap_or_default() && test.success) {
-                        Some(format!(
-                            "https://releases.nixos.org/{}/{test_name}",
-                            if channel == "nixpkgs-unstable" {
-                                "nixpkgs".into()
-                            } else {
-                                channel.replacen('-', "/", 1)
-                            }
-                        ))
-                    } else {
-                        None
-                    };
+        let is_default_host = client.is_default_host();
+        let is_channel = if is_default_host {
+            // see: https://channels.nixos.org
+            channel.starts_with("nixpkgs-") || channel.starts_with("nixos-")
+        } else {
+            !channel.is_empty()
+        };
+        let (release_url, git_revision, git_revision_url) = match client.release_url() {
+            Some(template) if is_channel => {
+                if let Some(test_name) = test.name.as_deref() {
+                    let release_url =
+                        if always_link || (eval.finished.unwrap_or_default() && test.success) {
+                            Some(expand_release_url(template, channel, test_name))
+                        } else {
+                            None
+                        };
 
-                let git_revision = test_name.rsplit_once('.').map(|(_, rev)| rev.to_string());
+                    let git_revision = test_name.rsplit_once('.').map(|(_, rev)| rev.to_string());
 
-                let git_revision_url = git_revision
-                    .as_deref()
-                    .map(|rev| format!("https://github.com/NixOS/nixpkgs/commits/{rev}"));
+                    let git_revision_url = git_revision
+                        .as_deref()
+                        .map(|rev| format!("https://github.com/NixOS/nixpkgs/commits/{rev}"));
 
-                (release_url, git_revision, git_revision_url)
-            } else {
-                Default::default() // None: no urls if no test.name
+                    (release_url, git_revision, git_revision_url)
+                } else {
+                    Default::default() // None: no urls if no test.name
+                }
             }
-        } else {
-            Default::default() // None: no urls
+            _ => Default::default(), // None: no urls
         };
-        let report_url = if constants::is_default_host_url()
+        let report_url = if is_default_host
             && (
                 jobset.starts_with("nixpkgs/") || jobset.starts_with("nixos/")
                 // see: https://channels.nixos.org
@@ -179,4 +197,69 @@ impl ReleaseStatus {
             git_revision_url,
         }
     }
+
+    /// Joins the recent `evals` of a `jobset` with the builds of its release
+    /// `tests`, by matching the short git revision of each eval against the
+    /// name of the test builds, e.g. `nixpkgs-25.11pre854150.5d8f4beac036`.
+    pub(crate) fn join(
+        evals: Vec<EvalStatus>,
+        tests: &[BuildStatus],
+        channel: &str,
+        jobset: &str,
+        client: &HydraClient,
+    ) -> Vec<Self> {
+        // mutable refs that is quick to remove from the front
+        let mut test_builds: VecDeque<&BuildStatus> = tests.iter().collect();
+
+        // if _all_ evals appear to be unfinished, it's likely that the
+        // instance is being rebooted, and we will always link to the
+        // releases as it's more practical
+        let always_link = evals.iter().all(|eval| !eval.finished.unwrap_or_default());
+
+        // this captures `test_builds` mutably but it does _not_ need
+        // to be marked as `mut` because it is moved into .filter_map()
+        // and re-borrowed as mut by them.
+        let filter_eval = |eval: EvalStatus| {
+            let short_rev = eval.short_rev.as_deref().unwrap_or_default();
+            for index in 0..test_builds.len() {
+                if test_builds[index]
+                    .name
+                    .as_deref()
+                    .unwrap_or_default()
+                    .contains(short_rev)
+                {
+                    let test = test_builds.remove(index)?.clone();
+                    return Some(Self::new(eval, test, channel, jobset, always_link, client));
+                }
+            }
+            None
+        };
+        evals.into_iter().filter_map(filter_eval).collect()
+    }
+}
+
+#[test]
+fn release_url_templates() {
+    let template = crate::constants::DEFAULT_RELEASE_URL;
+    let name = "nixos-24.11.711815.2d6cf8ef7f97";
+    assert_eq!(
+        expand_release_url(template, "nixos-24.11", name),
+        "https://releases.nixos.org/nixos/24.11/nixos-24.11.711815.2d6cf8ef7f97"
+    );
+    assert_eq!(
+        expand_release_url(
+            template,
+            "nixpkgs-unstable",
+            "nixpkgs-25.05pre1.5d8f4beac036"
+        ),
+        "https://releases.nixos.org/nixpkgs/nixpkgs-25.05pre1.5d8f4beac036"
+    );
+    assert_eq!(
+        expand_release_url(
+            "https://releases.example.org/{channel}/{name}",
+            "trunk",
+            name
+        ),
+        "https://releases.example.org/trunk/nixos-24.11.711815.2d6cf8ef7f97"
+    );
 }
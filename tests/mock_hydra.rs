@@ -0,0 +1,193 @@
+//! End-to-end tests of the command line against the mock Hydra server,
+//! run with `cargo test --features mock-hydra`.
+
+use hydra_check::mock::MockHydra;
+use serde_json::Value;
+use std::{process::Command, sync::OnceLock};
+
+/// URL of the mock server, which is shared by all the tests.
+fn mock_url() -> &'static str {
+    static URL: OnceLock<String> = OnceLock::new();
+    URL.get_or_init(|| {
+        let server = MockHydra::new().spawn("127.0.0.1:0").unwrap();
+        let url = server.url().to_string();
+        std::mem::forget(server); // keep serving until the tests exit
+        url
+    })
+}
+
+/// Runs hydra-check against the mock server, in an empty environment
+/// without any configuration, and returns its exit code and stdout.
+fn hydra_check(args: &[&str]) -> (i32, String) {
+    let home = std::env::temp_dir().join(format!("hydra-check-mock-{}", std::process::id()));
+    std::fs::create_dir_all(&home).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_hydra-check"))
+        .args(args)
+        .arg("--no-cache")
+        .current_dir(&home)
+        .env_clear()
+        .env("HOME", &home)
+        .env("NO_COLOR", "1")
+        .env("HYDRA_CHECK_HOST_URL", mock_url())
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let code = output.status.code().unwrap();
+    (code, stdout.replace(mock_url(), "http://mock"))
+}
+
+fn hydra_check_json(args: &[&str]) -> (i32, Value) {
+    let (code, stdout) = hydra_check(&[args, &["--json"]].concat());
+    (code, serde_json::from_str(&stdout).unwrap())
+}
+
+#[test]
+fn package_exit_codes() {
+    let jobset = ["--jobset", "nixpkgs/unstable"];
+    let (code, json) =
+        hydra_check_json(&[&jobset[..], &["hello", "--arch", "aarch64-linux"]].concat());
+    assert_eq!(code, 0);
+    let builds = json["hello.aarch64-linux"].as_array().unwrap();
+    assert_eq!(builds.len(), 3);
+    assert_eq!(builds[0]["build_url"], "http://mock/build/290062157");
+
+    // the latest build is still queued
+    let (code, _) = hydra_check(&[&jobset[..], &["hello", "--arch", "x86_64-linux"]].concat());
+    assert_eq!(code, 1);
+
+    let (code, json) =
+        hydra_check_json(&[&jobset[..], &["discord", "--arch", "x86_64-linux"]].concat());
+    assert_eq!(code, 4);
+    assert_eq!(
+        json["discord.x86_64-linux"]["error"]["kind"],
+        "not-evaluated"
+    );
+
+    let (code, json) =
+        hydra_check_json(&[&jobset[..], &["nonexistent", "--arch", "x86_64-linux"]].concat());
+    assert_eq!(code, 3);
+    assert_eq!(
+        json["nonexistent.x86_64-linux"]["error"]["kind"],
+        "not-found"
+    );
+}
+
+#[test]
+fn log_of_packages_only() {
+    let jobset = ["--jobset", "nixpkgs/unstable"];
+    let (code, _) =
+        hydra_check(&[&jobset[..], &["hello", "--arch", "aarch64-linux", "--log"]].concat());
+    assert_eq!(code, 0);
+    for args in [
+        &["--log"][..],
+        &["hello", "--arch", "all", "--log"],
+        &["--log", "bisect", "hello"],
+    ] {
+        let (code, stdout) = hydra_check(&[&jobset[..], args].concat());
+        assert_ne!(code, 0, "--log should be rejected for {args:?}");
+        assert!(stdout.is_empty());
+    }
+}
+
+#[test]
+fn releases() {
+    for backend in ["html", "json"] {
+        let args = [
+            "--backend",
+            backend,
+            "--jobset",
+            "nixpkgs/unstable",
+            "--releases",
+        ];
+        let (code, json) = hydra_check_json(&args);
+        assert_eq!(code, 0, "--releases with --backend {backend}");
+        let releases = json["nixpkgs/unstable"].as_array().unwrap();
+        let joined: Vec<_> = releases
+            .iter()
+            .map(|x| {
+                let id = x["eval"]["id"].as_u64().unwrap();
+                (id, x["test"]["success"].as_bool().unwrap())
+            })
+            .collect();
+        assert_eq!(
+            joined,
+            [(1_812_345, true), (1_812_301, false), (1_809_808, true)]
+        );
+        // the latest eval is still queued, which the JSON API cannot tell
+        assert_eq!(releases[0]["eval"]["icon"], "⧖");
+        assert_eq!(releases[0]["eval"]["finished"], false);
+        assert_eq!(releases[2]["eval"]["finished"], true);
+    }
+}
+
+#[test]
+fn evals_and_builds() {
+    for backend in ["html", "json"] {
+        let args = ["--backend", backend, "--jobset", "nixpkgs/unstable"];
+        let (code, json) = hydra_check_json(&[&args[..], &["--eval", "1809808/"]].concat());
+        assert_eq!(code, 0, "--eval with --backend {backend}");
+        let eval = &json["1809808"];
+        assert_eq!(eval["inputs"][0]["name"], "nixpkgs");
+        assert_eq!(eval["changes"][0]["short_revs"][0], "8c4dc69b9732");
+        assert_eq!(eval["now_fail"][0]["build_id"], "290021987");
+
+        let (code, json) = hydra_check_json(&[&args[..], &["--build", "290062156"]].concat());
+        assert_eq!(code, 0, "--build with --backend {backend}");
+        let build = &json["290062156"];
+        assert_eq!(build["name"], "hello-2.12.1");
+        assert_eq!(build["duration"], 48);
+        assert_eq!(build["inputs"][1]["name"], "officialRelease");
+    }
+}
+
+#[test]
+fn output_formats() {
+    let args = [
+        "--jobset",
+        "nixpkgs/unstable",
+        "hello",
+        "--arch",
+        "aarch64-linux",
+    ];
+    let (code, stdout) = hydra_check(&[&args[..], &["--format", "jsonl"]].concat());
+    assert_eq!(code, 0);
+    let builds: Vec<Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(builds.len(), 3);
+    assert_eq!(builds[1]["package"], "hello.aarch64-linux");
+    assert_eq!(builds[1]["status"], "Dependency failed");
+
+    let (_, stdout) = hydra_check(&[&args[..], &["--format", "csv"]].concat());
+    let mut rows = stdout.lines();
+    assert_eq!(
+        rows.next(),
+        Some("package,icon,success,status,timestamp,build_id,build_url,name,arch,evals")
+    );
+    assert_eq!(rows.count(), 3);
+
+    let (_, stdout) = hydra_check(&[&args[..], &["--format", "markdown"]].concat());
+    assert!(stdout.starts_with("### hello.aarch64-linux\n\n| icon | success |"));
+
+    let (_, stdout) = hydra_check(&[&args[..], &["--format", "yaml"]].concat());
+    assert!(stdout.starts_with("hello.aarch64-linux:\n  - icon: \"✔\"\n"));
+
+    let jobs = ["hello", "discord", "--arch", "x86_64-linux"];
+    let (code, stdout) = hydra_check(&[&args[..2], &jobs, &["--format", "junit"]].concat());
+    assert_eq!(code, 4);
+    assert!(stdout.contains(
+        r#"<testsuite name="nixpkgs/unstable" tests="2" failures="0" errors="1" skipped="1">"#
+    ));
+    assert!(stdout.contains(r#"<error type="not-evaluated" message="This job is not a member"#));
+
+    // the failures are in the gauges instead of the exit code
+    let (code, stdout) = hydra_check(&[&args[..2], &jobs, &["--format", "prometheus"]].concat());
+    assert_eq!(code, 0);
+    assert!(stdout.contains(
+        r#"hydra_check_job_success{jobset="nixpkgs/unstable",job="hello.x86_64-linux"} 0"#
+    ));
+    assert!(stdout
+        .contains(r#"hydra_check_jobset_builds{jobset="nixpkgs/unstable",status="failed"} 5624"#));
+    assert!(stdout.ends_with("# EOF\n"));
+}